@@ -0,0 +1,586 @@
+//! Macros used to describe keyboards in kludged.
+//!
+//! The generated code refers to kludged's own types through `crate::`, so the macros are only
+//! meant to be used from within kludged.
+
+use std::collections::HashMap;
+
+use proc_macro2::{Literal, Span, TokenStream};
+use quote::quote;
+use syn::{
+    braced,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Attribute, Error, Ident, Lit, LitInt, Token, Visibility,
+};
+
+/// Generate the key enum of a keyboard from where each key is, and where its color is sent.
+///
+/// The input starts with the enum's declaration, followed by:
+/// - `steps { .. }` with the number of steps in the color setting, their length, and the index
+///   the key data starts at in the first, and in the rest of the steps.
+/// - A `row { .. }` for each row of the keyboard, listing its keys from left to right. A key is
+///   one unit wide, unless its width follows it as in `Space: 6.25`. The enum's variants are
+///   declared in this order.
+/// - A `step N { .. }` for each step, with runs of keys as `INDEX: KEY, KEY, ..;`. The first key
+///   of a run takes the three bytes starting at `INDEX`, and every following key the next three.
+///   A key that does not fit at the end of a step continues at the start of the next one.
+///
+/// From these the enum is generated, along with:
+/// - `ROWS`, `indexes()`, `name()`, and `geometry()`.
+/// - [`FromStr`](std::str::FromStr), and [`Display`](std::fmt::Display) using the kebab-case
+///   names of the keys. Parsing is case insensitive.
+/// - `From<Key>`, and `From<&Key>` for `&'static str`, and `strum::VariantNames`.
+///
+/// Keys that are in more than one row, placed more than once, never placed, placed outside of
+/// the steps, or that overlap with another key are rejected at compile time.
+///
+/// ```ignore
+/// kludged_macros::layout! {
+///     /// Every key on the keyboard.
+///     #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+///     pub enum Keys;
+///
+///     steps { count: 2, len: 65, first: 6, rest: 3 }
+///
+///     row { Esc, Num1, BackSpace: 2.0 }
+///     row { Tab: 1.5, Q, Enter: 2.5 }
+///
+///     step 0 {
+///         9: Esc, Tab;
+///         // Takes the last two bytes of the first step, and the first byte of the second.
+///         63: Num1;
+///     }
+///     step 1 {
+///         4: Q, BackSpace, Enter;
+///     }
+/// }
+/// ```
+#[proc_macro]
+pub fn layout(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    syn::parse_macro_input!(input as Layout)
+        .expand()
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+struct Layout {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    ident: Ident,
+    steps: Steps,
+    rows: Vec<Vec<RowKey>>,
+    runs: Vec<Run>,
+}
+
+/// Where key data can be placed in the steps of the color setting.
+#[derive(Clone, Copy)]
+struct Steps {
+    count: usize,
+    len: usize,
+
+    /// Index the key data starts at in the first step.
+    first: usize,
+
+    /// Index the key data starts at in every other step.
+    rest: usize,
+}
+
+struct RowKey {
+    ident: Ident,
+    width: f32,
+}
+
+/// Keys placed one after the other, starting from an index of a step.
+struct Run {
+    step: usize,
+    step_span: Span,
+    index: LitInt,
+    keys: Vec<Ident>,
+}
+
+impl Parse for Layout {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        input.parse::<Token![enum]>()?;
+        let ident: Ident = input.parse()?;
+        input.parse::<Token![;]>()?;
+
+        let mut steps = None;
+        let mut rows = vec![];
+        let mut runs = vec![];
+
+        while !input.is_empty() {
+            let keyword: Ident = input.parse()?;
+
+            match keyword.to_string().as_str() {
+                "steps" if steps.is_some() => {
+                    return Err(Error::new(keyword.span(), "`steps` is already given"));
+                }
+                "steps" => steps = Some(parse_steps(input)?),
+                "row" => rows.push(parse_row(input)?),
+                "step" => runs.extend(parse_step(input)?),
+                _ => {
+                    return Err(Error::new(
+                        keyword.span(),
+                        "expected `steps`, `row`, or `step`",
+                    ))
+                }
+            }
+        }
+
+        let steps = steps.ok_or_else(|| Error::new(ident.span(), "missing `steps { .. }`"))?;
+
+        Ok(Self {
+            attrs,
+            vis,
+            ident,
+            steps,
+            rows,
+            runs,
+        })
+    }
+}
+
+/// Parse `{ count: 7, len: 65, first: 6, rest: 3 }`.
+fn parse_steps(input: ParseStream) -> syn::Result<Steps> {
+    let content;
+    let brace = braced!(content in input);
+    let fields =
+        Punctuated::<(Ident, LitInt), Token![,]>::parse_terminated_with(&content, |input| {
+            let name = input.parse()?;
+            input.parse::<Token![:]>()?;
+
+            Ok((name, input.parse()?))
+        })?;
+
+    let mut values: HashMap<String, usize> = HashMap::new();
+    for (name, value) in fields {
+        if !["count", "len", "first", "rest"].contains(&name.to_string().as_str()) {
+            return Err(Error::new(
+                name.span(),
+                "expected `count`, `len`, `first`, or `rest`",
+            ));
+        }
+
+        values.insert(name.to_string(), value.base10_parse()?);
+    }
+
+    let value = |name: &str| {
+        values
+            .get(name)
+            .copied()
+            .ok_or_else(|| Error::new(brace.span.join(), format!("missing `{name}`")))
+    };
+
+    Ok(Steps {
+        count: value("count")?,
+        len: value("len")?,
+        first: value("first")?,
+        rest: value("rest")?,
+    })
+}
+
+/// Parse `{ Tab: 1.5, Q, W }`.
+fn parse_row(input: ParseStream) -> syn::Result<Vec<RowKey>> {
+    let content;
+    braced!(content in input);
+    let keys = Punctuated::<RowKey, Token![,]>::parse_terminated(&content)?;
+
+    Ok(keys.into_iter().collect())
+}
+
+impl Parse for RowKey {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident = input.parse()?;
+        if !input.peek(Token![:]) {
+            return Ok(Self { ident, width: 1.0 });
+        }
+
+        input.parse::<Token![:]>()?;
+        let width = match input.parse()? {
+            Lit::Float(width) => width.base10_parse()?,
+            Lit::Int(width) => width.base10_parse()?,
+            lit => return Err(Error::new(lit.span(), "expected the width of the key")),
+        };
+
+        Ok(Self { ident, width })
+    }
+}
+
+/// Parse `0 { 9: Esc, Tab; 27: Num1 }`.
+fn parse_step(input: ParseStream) -> syn::Result<Vec<Run>> {
+    let step: LitInt = input.parse()?;
+    let content;
+    braced!(content in input);
+
+    let mut runs = vec![];
+    while !content.is_empty() {
+        let index = content.parse()?;
+        content.parse::<Token![:]>()?;
+        let keys = Punctuated::<Ident, Token![,]>::parse_separated_nonempty(&content)?;
+
+        runs.push(Run {
+            step: step.base10_parse()?,
+            step_span: step.span(),
+            index,
+            keys: keys.into_iter().collect(),
+        });
+
+        if !content.is_empty() {
+            content.parse::<Token![;]>()?;
+        }
+    }
+
+    Ok(runs)
+}
+
+impl Layout {
+    fn expand(&self) -> syn::Result<TokenStream> {
+        let keys: Vec<&RowKey> = self.rows.iter().flatten().collect();
+        for (i, key) in keys.iter().enumerate() {
+            if keys[..i].iter().any(|other| other.ident == key.ident) {
+                return Err(Error::new(
+                    key.ident.span(),
+                    format!("`{}` is in more than one row", key.ident),
+                ));
+            }
+        }
+
+        let indexes = self.indexes(&keys)?.into_iter().map(|indexes| {
+            let [(first_step, first), _, (last_step, last)] = indexes;
+            if first_step == last_step && last == first + 2 {
+                let (step, first) = (unsuffixed(first_step), unsuffixed(first));
+                return quote!(crate::keyboards::steps::same_step_indexes(#step, #first));
+            }
+
+            let indexes = indexes.map(|(step, index)| {
+                let (step, index) = (unsuffixed(step), unsuffixed(index));
+                quote!(crate::keyboards::steps::Indexes::new(#step, #index))
+            });
+            quote!([#(#indexes),*])
+        });
+
+        let geometry = self.rows.iter().enumerate().flat_map(|(row, keys)| {
+            let mut x = 0.0_f32;
+
+            keys.iter().enumerate().map(move |(column, key)| {
+                let geometry = geometry(row, column, x, key.width);
+                x += key.width;
+
+                geometry
+            })
+        });
+
+        let rows = self.rows.iter().map(|row| {
+            let keys = row.iter().map(|key| &key.ident);
+            quote!(&[#(Self::#keys),*])
+        });
+
+        let Self {
+            attrs, vis, ident, ..
+        } = self;
+        let variants: Vec<&Ident> = keys.iter().map(|key| &key.ident).collect();
+        let names: Vec<String> = variants
+            .iter()
+            .map(|variant| kebab_case(&variant.to_string()))
+            .collect();
+
+        Ok(quote! {
+            #(#attrs)*
+            #vis enum #ident {
+                #(#variants,)*
+            }
+
+            impl #ident {
+                /// Rows of the keyboard, each ordered from left to right.
+                pub const ROWS: &'static [&'static [Self]] = &[#(#rows),*];
+
+                /// Where the red, green, and blue values of the key are in the color setting.
+                pub const fn indexes(&self) -> [crate::keyboards::steps::Indexes; 3] {
+                    match self {
+                        #(Self::#variants => #indexes,)*
+                    }
+                }
+
+                /// The name of the key, in kebab-case.
+                pub const fn name(&self) -> &'static str {
+                    match self {
+                        #(Self::#variants => #names,)*
+                    }
+                }
+
+                /// Where the key physically is on the keyboard.
+                pub fn geometry(&self) -> crate::keyboards::layout::KeyGeometry {
+                    match self {
+                        #(Self::#variants => #geometry,)*
+                    }
+                }
+            }
+
+            impl ::core::str::FromStr for #ident {
+                type Err = ::strum::ParseError;
+
+                fn from_str(s: &str) -> ::core::result::Result<Self, Self::Err> {
+                    #(
+                        if s.eq_ignore_ascii_case(#names) {
+                            return Ok(Self::#variants);
+                        }
+                    )*
+
+                    Err(::strum::ParseError::VariantNotFound)
+                }
+            }
+
+            impl ::core::fmt::Display for #ident {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    f.pad(self.name())
+                }
+            }
+
+            impl ::core::convert::From<#ident> for &'static str {
+                #[inline(always)]
+                fn from(value: #ident) -> Self {
+                    value.name()
+                }
+            }
+
+            impl ::core::convert::From<&#ident> for &'static str {
+                #[inline(always)]
+                fn from(value: &#ident) -> Self {
+                    value.name()
+                }
+            }
+
+            impl ::strum::VariantNames for #ident {
+                const VARIANTS: &'static [&'static str] = &[#(#names),*];
+            }
+        })
+    }
+
+    /// The `(step, index)` of the red, green, and blue values of each key.
+    fn indexes(&self, keys: &[&RowKey]) -> syn::Result<Vec<[(usize, usize); 3]>> {
+        let Steps {
+            count,
+            len,
+            first,
+            rest,
+        } = self.steps;
+
+        let mut placed: Vec<Option<[(usize, usize); 3]>> = vec![None; keys.len()];
+
+        // The key that uses each byte.
+        let mut used: HashMap<(usize, usize), &Ident> = HashMap::new();
+
+        for run in &self.runs {
+            if run.step >= count {
+                return Err(Error::new(
+                    run.step_span,
+                    format!(
+                        "step {} is out of bounds, there are {count} steps",
+                        run.step
+                    ),
+                ));
+            }
+
+            let start = if run.step == 0 { first } else { rest };
+            let mut step = run.step;
+            let mut index: usize = run.index.base10_parse()?;
+
+            if !(start..len).contains(&index) {
+                return Err(Error::new(
+                    run.index.span(),
+                    format!(
+                        "index {index} is out of bounds, keys in step {step} are in {start}..{len}"
+                    ),
+                ));
+            }
+
+            for ident in &run.keys {
+                let position =
+                    keys.iter()
+                        .position(|key| key.ident == *ident)
+                        .ok_or_else(|| {
+                            Error::new(ident.span(), format!("`{ident}` is not in any row"))
+                        })?;
+
+                if placed[position].is_some() {
+                    return Err(Error::new(
+                        ident.span(),
+                        format!("`{ident}` is placed more than once"),
+                    ));
+                }
+
+                let mut indexes = [(0, 0); 3];
+                for indexes in &mut indexes {
+                    // Keys that do not fit at the end of a step continue in the next one.
+                    if index == len {
+                        step += 1;
+                        index = rest;
+                    }
+
+                    if step >= count {
+                        return Err(Error::new(
+                            ident.span(),
+                            format!("`{ident}` runs past the last step"),
+                        ));
+                    }
+
+                    if let Some(other) = used.insert((step, index), ident) {
+                        return Err(Error::new(
+                            ident.span(),
+                            format!("`{ident}` overlaps `{other}` at step {step}, index {index}"),
+                        ));
+                    }
+
+                    *indexes = (step, index);
+                    index += 1;
+                }
+
+                placed[position] = Some(indexes);
+            }
+        }
+
+        keys.iter()
+            .zip(placed)
+            .map(|(key, indexes)| {
+                indexes.ok_or_else(|| {
+                    Error::new(
+                        key.ident.span(),
+                        format!("`{}` is not placed in any step", key.ident),
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+fn geometry(row: usize, column: usize, x: f32, width: f32) -> TokenStream {
+    let y = Literal::f32_unsuffixed(row as f32);
+    let (row, column) = (unsuffixed(row), unsuffixed(column));
+    let (x, width) = (Literal::f32_unsuffixed(x), Literal::f32_unsuffixed(width));
+
+    quote! {
+        crate::keyboards::layout::KeyGeometry {
+            row: #row,
+            column: #column,
+            x: #x,
+            y: #y,
+            width: #width,
+            height: 1.0,
+        }
+    }
+}
+
+#[inline(always)]
+fn unsuffixed(value: usize) -> Literal {
+    Literal::usize_unsuffixed(value)
+}
+
+/// Convert a variant name to kebab-case the same way serde does, such as `LeftShift` to
+/// `left-shift`.
+fn kebab_case(name: &str) -> String {
+    let mut kebab = String::with_capacity(name.len() + 4);
+
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            kebab.push('-');
+        }
+        kebab.push(c.to_ascii_lowercase());
+    }
+
+    kebab
+}
+
+#[cfg(test)]
+mod tests {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use super::{kebab_case, Layout};
+
+    fn expand(steps: TokenStream) -> Result<String, String> {
+        let input = quote! {
+            pub enum Keys;
+
+            steps { count: 2, len: 12, first: 6, rest: 3 }
+
+            row { Esc, Num1: 2.0 }
+            row { Tab: 1.5, Q }
+
+            #steps
+        };
+
+        syn::parse2::<Layout>(input)
+            .and_then(|layout| layout.expand())
+            .map(|tokens| tokens.to_string())
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_expand() {
+        let tokens = expand(quote! {
+            step 0 { 6: Esc; 10: Num1 }
+            step 1 { 4: Tab, Q }
+        })
+        .unwrap();
+
+        assert!(tokens
+            .contains("Self :: Esc => crate :: keyboards :: steps :: same_step_indexes (0 , 6)"));
+        assert!(tokens.contains(
+            "Self :: Num1 => [crate :: keyboards :: steps :: Indexes :: new (0 , 10) , \
+            crate :: keyboards :: steps :: Indexes :: new (0 , 11) , \
+            crate :: keyboards :: steps :: Indexes :: new (1 , 3)]"
+        ));
+        assert!(tokens
+            .contains("Self :: Q => crate :: keyboards :: steps :: same_step_indexes (1 , 7)"));
+        assert!(tokens.contains("row : 1 , column : 1 , x : 1.5 , y : 1.0 , width : 1.0"));
+        assert!(tokens.contains("[\"esc\" , \"num1\" , \"tab\" , \"q\"]"));
+    }
+
+    #[test]
+    fn test_invalid_placement() {
+        let cases = [
+            (
+                quote!(step 0 { 6: Esc, Num1; 9: Tab } step 1 { 6: Q }),
+                "`Tab` overlaps `Num1` at step 0, index 9",
+            ),
+            (
+                quote!(step 0 { 3: Esc, Num1 } step 1 { 3: Tab, Q }),
+                "index 3 is out of bounds, keys in step 0 are in 6..12",
+            ),
+            (
+                quote!(step 0 { 6: Esc, Num1 } step 2 { 3: Tab, Q }),
+                "step 2 is out of bounds, there are 2 steps",
+            ),
+            (
+                quote!(step 0 { 6: Esc, Num1 } step 1 { 3: Tab; 10: Q }),
+                "`Q` runs past the last step",
+            ),
+            (
+                quote!(step 0 { 6: Esc, Num1 } step 1 { 3: Tab }),
+                "`Q` is not placed in any step",
+            ),
+            (
+                quote!(step 0 { 6: Esc, Num1 } step 1 { 3: Tab, Esc }),
+                "`Esc` is placed more than once",
+            ),
+            (
+                quote!(step 0 { 6: Esc, Num1 } step 1 { 3: Tab, Q, W }),
+                "`W` is not in any row",
+            ),
+        ];
+
+        for (steps, err) in cases {
+            assert_eq!(expand(steps), Err(err.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_kebab_case() {
+        assert_eq!(kebab_case("LeftShift"), "left-shift");
+        assert_eq!(kebab_case("Num1"), "num1");
+        assert_eq!(kebab_case("Q"), "q");
+    }
+}
@@ -1,10 +1,10 @@
-use std::str::FromStr;
-
 use clap::Arg;
 use color_print::cformat;
-use palette::{named::from_str, Srgb};
+use palette::Srgb;
+
+use crate::keyboards::color::parse_color;
 
-use super::errors::InvalidColor;
+use super::errors::{InvalidColor, InvalidStop, InvalidUsbId};
 
 pub fn color_arg() -> Arg {
     Arg::new("color")
@@ -20,15 +20,54 @@ pub fn show_keyboard(kb: &str) -> String {
 }
 
 pub fn get_color(arg: &str) -> Result<Srgb<u8>, InvalidColor> {
-    if let Some(color) = from_str(arg) {
-        Ok(color)
-    } else {
-        Srgb::<u8>::from_str(arg).map_err(|_| InvalidColor)
+    parse_color(arg).ok_or(InvalidColor)
+}
+
+/// Parse a gradient stop such as `"red"`, `"#00ff00@0.25"`, or `"blue@75%"`.
+///
+/// The position is optional, and is returned as a fraction from `0.0` to `1.0`.
+pub fn get_stop(arg: &str) -> Result<(Option<f32>, Srgb<u8>), InvalidStop> {
+    let Some((color, position)) = arg.rsplit_once('@') else {
+        return Ok((None, get_color(arg.trim())?));
+    };
+
+    let position = position.trim();
+    let position = match position.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f32>().map(|p| p / 100.0),
+        None => position.parse::<f32>(),
     }
+    .ok()
+    .filter(|p| (0.0..=1.0).contains(p))
+    .ok_or(InvalidStop::InvalidPosition)?;
+
+    Ok((Some(position), get_color(color.trim())?))
+}
+
+/// Parse a USB ID such as `"258a:005e"`, as printed by `lsusb`, into the vendor, and product ID.
+pub fn get_usb_id(arg: &str) -> Result<(u16, u16), InvalidUsbId> {
+    let (vid, pid) = arg.split_once(':').ok_or(InvalidUsbId)?;
+    let parse = |id: &str| u16::from_str_radix(id.trim(), 16).map_err(|_| InvalidUsbId);
+
+    Ok((parse(vid)?, parse(pid)?))
+}
+
+/// Find the closest match to `value` in `candidates`.
+///
+/// Uses the same similarity metric, and threshold as clap does when suggesting flags.
+pub fn did_you_mean<I>(value: &str, candidates: I) -> Option<&'static str>
+where
+    I: IntoIterator<Item = &'static str>,
+{
+    candidates
+        .into_iter()
+        .map(|candidate| (strsim::jaro(value, candidate), candidate))
+        .filter(|(confidence, _)| *confidence > 0.7)
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, candidate)| candidate)
 }
 
 /// A quick way to create a [`clap::builder::PossibleValuesParser`], using a type that implements
-/// [`strum::VariantNames`], and [`FromStr`].
+/// [`strum::VariantNames`], and [`std::str::FromStr`].
 #[macro_export]
 macro_rules! possible_values {
     ($ty:ty) => {
@@ -1,5 +1,7 @@
 use std::{error::Error, fmt::Display};
 
+use crate::errors::InvalidSelection;
+
 #[derive(Clone, Debug)]
 pub struct InvalidColor;
 
@@ -13,3 +15,125 @@ impl Display for InvalidColor {
         )
     }
 }
+
+#[derive(Clone, Debug)]
+pub struct InvalidKey {
+    pub key: String,
+    pub suggestion: Option<&'static str>,
+}
+
+impl Error for InvalidKey {}
+
+impl Display for InvalidKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, r#"Unknown key, or key group "{}"."#, self.key)?;
+
+        if let Some(suggestion) = self.suggestion {
+            write!(f, "\n\n  tip: a similar key exists: '{suggestion}'")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum InvalidKeyColor {
+    MissingColor,
+    InvalidKey(InvalidKey),
+    InvalidSelection(InvalidSelection),
+    InvalidColor(InvalidColor),
+}
+
+impl Error for InvalidKeyColor {}
+
+impl Display for InvalidKeyColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingColor => write!(
+                f,
+                r#"Key colors must be provided as KEYS=COLOR, such as "esc=red"."#
+            ),
+            Self::InvalidKey(err) => err.fmt(f),
+            Self::InvalidSelection(err) => err.fmt(f),
+            Self::InvalidColor(err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<InvalidKey> for InvalidKeyColor {
+    fn from(value: InvalidKey) -> Self {
+        Self::InvalidKey(value)
+    }
+}
+
+impl From<InvalidSelection> for InvalidKeyColor {
+    fn from(value: InvalidSelection) -> Self {
+        Self::InvalidSelection(value)
+    }
+}
+
+impl From<InvalidColor> for InvalidKeyColor {
+    fn from(value: InvalidColor) -> Self {
+        Self::InvalidColor(value)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum InvalidStop {
+    InvalidPosition,
+    InvalidColor(InvalidColor),
+}
+
+impl Error for InvalidStop {}
+
+impl Display for InvalidStop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPosition => write!(
+                f,
+                r#"Stop positions must be between 0 and 1, or 0% and 100%, such as "red@0.5", or "red@50%"."#
+            ),
+            Self::InvalidColor(err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<InvalidColor> for InvalidStop {
+    fn from(value: InvalidColor) -> Self {
+        Self::InvalidColor(value)
+    }
+}
+
+/// A line of a hex file that is not made up of hex bytes.
+#[derive(Clone, Debug)]
+pub struct InvalidHex {
+    /// The line, counting from one.
+    pub line: usize,
+}
+
+impl Error for InvalidHex {}
+
+impl Display for InvalidHex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            r#"Line {} is not a report, reports must be written as hex bytes such as "0a 07 01"."#,
+            self.line
+        )
+    }
+}
+
+/// A USB ID that is not written as `VID:PID`.
+#[derive(Clone, Debug)]
+pub struct InvalidUsbId;
+
+impl Error for InvalidUsbId {}
+
+impl Display for InvalidUsbId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            r#"The ID must be a vendor, and product ID in hex, such as "258a:005e"."#
+        )
+    }
+}
@@ -0,0 +1,271 @@
+use std::{path::PathBuf, sync::Arc};
+
+use clap::{builder::PossibleValuesParser, Arg, ArgAction, ArgMatches, Command, FromArgMatches};
+use palette::Srgb;
+
+use crate::{
+    cli::{
+        commons::{color_arg, get_color},
+        errors::{InvalidKey, InvalidKeyColor},
+    },
+    keyboards::{
+        capture::Capture,
+        definition::{ModelDefinition, OptionDefinition},
+        generic::GenericKeyboard,
+        policy::WritePolicy,
+        selector::Target,
+        transport::{DryRunTransport, Transport},
+    },
+};
+
+/// Construct the subcommand of a model described by a definition.
+pub fn command(cmd: Command, definition: &ModelDefinition) -> Command {
+    let model_cmd = Command::new(definition.name.clone())
+        .subcommands(single_kb_command(definition))
+        .subcommand_required(true);
+    cmd.subcommand(model_cmd)
+}
+
+/// Construct the inner subcommands of a model described by a definition.
+///
+/// The same subcommands as the built in models are provided, as far as the definition describes
+/// them. Each option of a setting becomes an argument of the same name.
+pub fn single_kb_command(definition: &ModelDefinition) -> Vec<Command> {
+    let keys: Vec<String> = definition.keys.iter().map(|key| key.key.clone()).collect();
+    let color_options = option_args(&definition.color.options);
+
+    let mut commands = vec![
+        Command::new("set-color")
+            .about("Set the color of the keyboard.")
+            .arg(color_arg().required(true))
+            .args(color_options.clone()),
+        Command::new("set-keys")
+            .about("Set the color of individual keys.")
+            .args([
+                Arg::new("key")
+                    .short('k')
+                    .long("key")
+                    .value_name("KEYS=COLOR")
+                    .value_parser(move |arg: &str| get_key_color(arg, &keys))
+                    .action(ArgAction::Append)
+                    .required(true)
+                    .help("Set the color of keys, can be provided multiple times.")
+                    .long_help(
+                        "Set the color of keys, such as \"esc=red\", or \"esc,tab=#00ff00\". \
+                        Can be provided multiple times, later values override earlier ones.",
+                    ),
+                Arg::new("default")
+                    .short('d')
+                    .long("default")
+                    .value_name("COLOR")
+                    .value_parser(get_color)
+                    .default_value("black")
+                    .help("Color of the keys that were not provided."),
+            ])
+            .args(color_options),
+    ];
+
+    if let Some(animation) = &definition.animation {
+        let mut cmd = Command::new("set-anim")
+            .about("Set the animation of the keyboard.")
+            .arg(
+                Arg::new("anim")
+                    .short('a')
+                    .long("anim")
+                    .value_parser(PossibleValuesParser::new(&animation.animation.values))
+                    .required(true),
+            )
+            .args(option_args(&animation.report.options));
+
+        if animation.color.is_some() {
+            cmd = cmd.arg(color_arg());
+        }
+
+        commands.push(cmd);
+    }
+
+    commands
+}
+
+/// An argument for each option, named after it.
+fn option_args(options: &[OptionDefinition]) -> Vec<Arg> {
+    options
+        .iter()
+        .map(|option| {
+            let values = &option.values;
+            let default = values.default.as_ref().unwrap_or(&values.values[0]);
+
+            Arg::new(option.name.clone())
+                .long(option.name.clone())
+                .value_parser(PossibleValuesParser::new(&values.values))
+                .default_value(default.clone())
+        })
+        .collect()
+}
+
+/// Parse a `KEYS=COLOR` assignment, where `KEYS` is a comma separated list of key names.
+fn get_key_color(arg: &str, keys: &[String]) -> Result<(Vec<String>, Srgb<u8>), InvalidKeyColor> {
+    let (names, color) = arg.split_once('=').ok_or(InvalidKeyColor::MissingColor)?;
+
+    let names = names
+        .split(',')
+        .map(|name| {
+            let name = name.trim().to_ascii_lowercase();
+
+            match keys.contains(&name) {
+                true => Ok(name),
+                false => Err(InvalidKey {
+                    key: name,
+                    suggestion: None,
+                }),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok((names, get_color(color.trim())?))
+}
+
+pub fn handle_args(
+    definition: &Arc<ModelDefinition>,
+    arg_matches: &ArgMatches,
+) -> anyhow::Result<()> {
+    let target = Target::from_arg_matches(arg_matches)?;
+    let write_policy = WritePolicy::from_arg_matches(arg_matches)?;
+    let capture = arg_matches
+        .get_one::<PathBuf>("capture")
+        .map(Capture::create)
+        .transpose()?;
+
+    // Ok to unwrap, subcommand is required.
+    let subcommand = arg_matches.subcommand().unwrap();
+
+    if arg_matches.get_flag("dry_run") {
+        let transport = DryRunTransport::new();
+        let kb = GenericKeyboard::with_transport(Arc::clone(definition), transport.clone());
+        apply(subcommand, kb.with_capture(capture.clone()))?;
+
+        // The reports are printed the same way `kludged decode` reads them.
+        for report in transport.reports() {
+            let hex: Vec<_> = report.iter().map(|byte| format!("{byte:02x}")).collect();
+            println!("{}", hex.join(" "));
+        }
+    } else {
+        for kb in GenericKeyboard::from_target(definition, &target)? {
+            apply(
+                subcommand,
+                kb.with_write_policy(write_policy)
+                    .with_capture(capture.clone()),
+            )?;
+        }
+    }
+
+    if let Some(capture) = &capture {
+        capture.finish()?;
+    }
+
+    Ok(())
+}
+
+/// Apply the setting described by one of the subcommands from [`single_kb_command`].
+fn apply<T: Transport>(
+    (name, arg_matches): (&str, &ArgMatches),
+    mut kb: GenericKeyboard<T>,
+) -> anyhow::Result<()> {
+    let definition = kb.definition().clone();
+    let option = |option: &OptionDefinition| -> (String, String) {
+        // Ok to unwrap, every option has a default value.
+        let value: &String = arg_matches.get_one(&option.name).unwrap();
+
+        (option.name.clone(), value.clone())
+    };
+
+    match name {
+        "set-color" | "set-keys" => {
+            kb = if name == "set-color" {
+                // Ok to unwrap as we require the argument.
+                kb.set_color(*arg_matches.get_one::<Srgb<u8>>("color").unwrap())
+            } else {
+                let mut kb = kb.set_color(*arg_matches.get_one::<Srgb<u8>>("default").unwrap());
+
+                for (keys, color) in arg_matches
+                    .get_many::<(Vec<String>, Srgb<u8>)>("key")
+                    .unwrap()
+                {
+                    for key in keys {
+                        kb = kb.set_key_color(key, *color)?;
+                    }
+                }
+
+                kb
+            };
+
+            for (option, value) in definition.color.options.iter().map(option) {
+                kb = kb.set_color_option(&option, &value)?;
+            }
+
+            kb.apply_color()?;
+        }
+        "set-anim" => {
+            // Ok to unwrap, the subcommand only exists for models with animations.
+            let animation = definition.animation.as_ref().unwrap();
+
+            kb = kb.set_animation(arg_matches.get_one::<String>("anim").unwrap())?;
+
+            if let Some(color) = arg_matches.try_get_one::<Srgb<u8>>("color").ok().flatten() {
+                kb = kb.set_animation_color(*color)?;
+            }
+
+            for (option, value) in animation.report.options.iter().map(option) {
+                kb = kb.set_animation_option(&option, &value)?;
+            }
+
+            kb.apply_animation()?;
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use crate::{
+        cli::{rk68, Cli},
+        keyboards::definition::{ModelDefinition, RESERVED_MODEL_NAMES, RESERVED_OPTION_NAMES},
+    };
+
+    use super::single_kb_command;
+
+    /// Every name a definition could collide with is reserved.
+    #[test]
+    fn test_reserved_names() {
+        let cmd = Cli::command().subcommands(rk68::single_kb_command());
+        for subcommand in cmd.get_subcommands() {
+            assert!(RESERVED_MODEL_NAMES.contains(&subcommand.get_name()));
+        }
+
+        let definition =
+            ModelDefinition::from_toml(include_str!("../../models/rk68.toml")).unwrap();
+        let options: Vec<&str> = definition
+            .color
+            .options
+            .iter()
+            .chain(definition.animation.iter().flat_map(|a| &a.report.options))
+            .map(|option| option.name.as_str())
+            .collect();
+
+        let commands = single_kb_command(&definition);
+        let args = cmd
+            .get_arguments()
+            .chain(commands.iter().flat_map(|cmd| cmd.get_arguments()));
+
+        for arg in args.filter_map(|arg| arg.get_long()) {
+            assert!(
+                options.contains(&arg) || RESERVED_OPTION_NAMES.contains(&arg),
+                "{arg}"
+            );
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use clap::{error::ErrorKind, value_parser, Arg, ArgAction, Args, FromArgMatches};
+
+use crate::keyboards::{
+    policy::{WritePolicy, BACKOFF_ENV, DELAY_ENV, PACKET_RETRIES_ENV, TRANSACTION_RETRIES_ENV},
+    selector::{DeviceSelector, Target, DEVICE_ENV},
+};
+
+impl Args for WritePolicy {
+    fn augment_args(cmd: clap::Command) -> clap::Command {
+        cmd.next_help_heading("Write Options").args([
+            Arg::new("packet_delay")
+                .long("packet-delay")
+                .value_name("MS")
+                .value_parser(value_parser!(u64))
+                .global(true)
+                .help(format!(
+                    "Milliseconds to wait after each packet. [env: {DELAY_ENV}] [default: 5]"
+                )),
+            Arg::new("packet_retries")
+                .long("packet-retries")
+                .value_name("COUNT")
+                .value_parser(value_parser!(u32))
+                .global(true)
+                .help(format!(
+                    "Times a failed packet is retried. [env: {PACKET_RETRIES_ENV}] [default: 2]"
+                )),
+            Arg::new("retry_backoff")
+                .long("retry-backoff")
+                .value_name("MS")
+                .value_parser(value_parser!(u64))
+                .global(true)
+                .help(format!(
+                    "Milliseconds to wait before the first retry, doubled on each retry. \
+                    [env: {BACKOFF_ENV}] [default: 10]"
+                )),
+            Arg::new("transaction_retries")
+                .long("transaction-retries")
+                .value_name("COUNT")
+                .value_parser(value_parser!(u32))
+                .global(true)
+                .help(format!(
+                    "Times a setting is sent again from the first packet, if a packet keeps \
+                    failing. [env: {TRANSACTION_RETRIES_ENV}] [default: 1]"
+                )),
+        ])
+    }
+
+    fn augment_args_for_update(cmd: clap::Command) -> clap::Command {
+        Self::augment_args(cmd)
+    }
+}
+
+impl FromArgMatches for WritePolicy {
+    fn from_arg_matches(matches: &clap::ArgMatches) -> Result<Self, clap::Error> {
+        let mut policy = WritePolicy::from_env()
+            .map_err(|err| clap::Error::raw(ErrorKind::InvalidValue, format!("{err}\n")))?;
+        policy.update_from_arg_matches(matches)?;
+
+        Ok(policy)
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &clap::ArgMatches) -> Result<(), clap::Error> {
+        // The arguments are optional so that the environment variables can be used as defaults.
+        if let Some(delay) = matches.get_one::<u64>("packet_delay") {
+            self.delay = Duration::from_millis(*delay);
+        }
+        if let Some(retries) = matches.get_one::<u32>("packet_retries") {
+            self.packet_retries = *retries;
+        }
+        if let Some(backoff) = matches.get_one::<u64>("retry_backoff") {
+            self.backoff = Duration::from_millis(*backoff);
+        }
+        if let Some(retries) = matches.get_one::<u32>("transaction_retries") {
+            self.transaction_retries = *retries;
+        }
+
+        Ok(())
+    }
+}
+
+impl Args for Target {
+    fn augment_args(cmd: clap::Command) -> clap::Command {
+        cmd.next_help_heading("Device Options").args([
+            Arg::new("device")
+                .long("device")
+                .value_name("PATH|SERIAL|INDEX")
+                .value_parser(|s: &str| s.parse::<DeviceSelector>())
+                .global(true)
+                .help(format!(
+                    "Keyboard to write to, when several of the same model are plugged in. \
+                    Use `kludged list` to find the path, serial, or index. Prefix it with \
+                    `path:`, `serial:`, or `index:` if it could be read as another, such as \
+                    `serial:0001`. [env: {DEVICE_ENV}]"
+                )),
+            Arg::new("all")
+                .long("all")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("device")
+                .global(true)
+                .help("Write to every keyboard of the model."),
+        ])
+    }
+
+    fn augment_args_for_update(cmd: clap::Command) -> clap::Command {
+        Self::augment_args(cmd)
+    }
+}
+
+impl FromArgMatches for Target {
+    fn from_arg_matches(matches: &clap::ArgMatches) -> Result<Self, clap::Error> {
+        let mut target = Target::from_env();
+        target.update_from_arg_matches(matches)?;
+
+        Ok(target)
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &clap::ArgMatches) -> Result<(), clap::Error> {
+        if let Some(selector) = matches.get_one::<DeviceSelector>("device") {
+            *self = Target::Device(selector.clone());
+        }
+        // Overrides a device set through the environment.
+        if matches.get_flag("all") {
+            *self = Target::All;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::io::{self, Write};
+
+use strum::{EnumString, VariantNames};
+
+use crate::keyboards::info::KeyboardInfo;
+
+/// How `kludged list` prints the detected keyboards.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, VariantNames, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum ListFormat {
+    /// Aligned columns with a header, meant to be read by people.
+    #[default]
+    Table,
+
+    /// A JSON array, with one object per keyboard.
+    Json,
+
+    /// One keyboard per line, with tab separated fields and no header. Missing values are
+    /// printed as `-`.
+    Plain,
+}
+
+const HEADER: [&str; 9] = [
+    "MODEL",
+    "VID:PID",
+    "INTERFACE",
+    "USAGE PAGE:USAGE",
+    "PATH",
+    "SERIAL",
+    "MANUFACTURER",
+    "PRODUCT",
+    "RELEASE",
+];
+
+fn fields(info: &KeyboardInfo) -> [String; 9] {
+    let or_dash = |s: &Option<String>| s.clone().unwrap_or_else(|| "-".to_owned());
+
+    [
+        info.model.clone(),
+        format!("{:04x}:{:04x}", info.vendor_id, info.product_id),
+        info.interface_number.to_string(),
+        format!("{:04x}:{:04x}", info.usage_page, info.usage),
+        info.path.clone(),
+        or_dash(&info.serial_number),
+        or_dash(&info.manufacturer),
+        or_dash(&info.product),
+        info.release(),
+    ]
+}
+
+/// Write the keyboards to `w` in the provided format.
+///
+/// An empty list is written as a table header, `[]`, or nothing at all respectively, so that
+/// scripts do not need to special case it.
+pub fn write_list<W: Write>(
+    w: &mut W,
+    keyboards: &[KeyboardInfo],
+    format: ListFormat,
+) -> io::Result<()> {
+    match format {
+        ListFormat::Table => {
+            let rows: Vec<_> = keyboards.iter().map(fields).collect();
+
+            let mut widths = HEADER.map(str::len);
+            for row in &rows {
+                for (width, field) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(field.len());
+                }
+            }
+
+            let mut write_row = |row: &mut dyn Iterator<Item = &str>| {
+                let line = row
+                    .zip(widths)
+                    .map(|(field, width)| format!("{field:width$}"))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+
+                writeln!(w, "{}", line.trim_end())
+            };
+
+            write_row(&mut HEADER.iter().copied())?;
+            for row in &rows {
+                write_row(&mut row.iter().map(String::as_str))?;
+            }
+        }
+        ListFormat::Json => {
+            serde_json::to_writer_pretty(&mut *w, keyboards)?;
+            writeln!(w)?;
+        }
+        ListFormat::Plain => {
+            for info in keyboards {
+                writeln!(w, "{}", fields(info).join("\t"))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keyboards::info::KeyboardInfo;
+
+    use super::{write_list, ListFormat};
+
+    fn info() -> KeyboardInfo {
+        KeyboardInfo {
+            model: "rk68".to_owned(),
+            vendor_id: 0x258a,
+            product_id: 0x005e,
+            interface_number: 1,
+            usage_page: 0xff00,
+            usage: 0x0001,
+            path: "/dev/hidraw3".to_owned(),
+            serial_number: None,
+            manufacturer: Some("Royal Kludge".to_owned()),
+            product: Some("RK68".to_owned()),
+            release_number: 0x0104,
+        }
+    }
+
+    fn list(keyboards: &[KeyboardInfo], format: ListFormat) -> String {
+        let mut buf = vec![];
+        write_list(&mut buf, keyboards, format).unwrap();
+
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_list() {
+        let table = list(&[info()], ListFormat::Table);
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("MODEL  VID:PID    INTERFACE"));
+        assert!(lines[1].starts_with("rk68   258a:005e  1"));
+
+        assert_eq!(
+            list(&[info()], ListFormat::Plain),
+            "rk68\t258a:005e\t1\tff00:0001\t/dev/hidraw3\t-\tRoyal Kludge\tRK68\t1.04\n"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&list(&[info()], ListFormat::Json)).unwrap();
+        assert_eq!(json[0]["vendor-id"], 0x258a);
+        assert_eq!(json[0]["serial-number"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_list_empty() {
+        assert_eq!(list(&[], ListFormat::Json), "[]\n");
+        assert_eq!(list(&[], ListFormat::Plain), "");
+        assert_eq!(list(&[], ListFormat::Table).lines().count(), 1);
+    }
+}
@@ -1,12 +1,27 @@
 pub mod commons;
 pub mod errors;
+pub mod generic;
+pub mod impls;
+pub mod list;
+pub mod probe;
 pub mod rk68;
 
-use std::path::PathBuf;
+use std::{path::PathBuf, str::FromStr};
 
-use clap::{clap_derive::Parser, Subcommand};
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    clap_derive::Parser,
+    Subcommand,
+};
 use clap_verbosity_flag::Verbosity;
 
+use crate::{
+    keyboards::{policy::WritePolicy, selector::Target},
+    possible_values,
+};
+
+use self::{commons::get_usb_id, list::ListFormat};
+
 #[derive(Debug, Parser)]
 pub struct Cli {
     #[arg(short, long)]
@@ -19,6 +34,33 @@ pub struct Cli {
     /// Set the level of verbosity.
     pub verbosity: Verbosity,
 
+    #[command(flatten)]
+    pub write_policy: WritePolicy,
+
+    #[command(flatten)]
+    pub target: Target,
+
+    /// Show the commands for a model, even if no such keyboard is detected.
+    ///
+    /// Either a built in model, such as rk68, or the name of a model described in the
+    /// configuration directory. Must be passed before the keyboard's commands. Mostly useful
+    /// together with --dry-run, or to read the help of a keyboard that is not plugged in.
+    #[arg(long, global = true)]
+    pub model: Option<String>,
+
+    /// Record every packet sent to, and read from the keyboard to a pcapng file.
+    ///
+    /// The packets are stored as USB control transfers in the usbmon format, and can be opened
+    /// with Wireshark. Can be used together with --dry-run.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub capture: Option<PathBuf>,
+
+    /// Print the packets that would be sent, instead of writing them to the keyboard.
+    ///
+    /// Each packet is printed as a hex dump, along with what each of its bytes is for.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -34,4 +76,58 @@ pub enum Commands {
         #[clap(default_value=UDEV_PATH)]
         path: PathBuf,
     },
+
+    /// List every detected keyboard.
+    ///
+    /// Useful for checking if a keyboard is detected, and which device it was found on.
+    List {
+        /// Format to print the keyboards in.
+        #[arg(short, long, default_value = "table", value_parser = possible_values!(ListFormat))]
+        format: ListFormat,
+    },
+
+    /// Decode the reports sent to a keyboard back into settings.
+    ///
+    /// Reads a pcap, or pcapng capture of USB traffic, or a text file with one report per line in
+    /// hex. Bytes that differ from what kludged sends are reported. Only the RK68 is supported.
+    Decode {
+        /// The capture, or hex file.
+        file: PathBuf,
+    },
+
+    /// Find out which key each color slot of a keyboard lights up.
+    ///
+    /// Lights one slot at a time, and asks which key lit up. Meant for adding keyboards that use
+    /// the same protocol as the RK68, the answers are written out as a layout file.
+    Probe {
+        /// Vendor, and product ID of the keyboard in hex. Defaults to the RK68.
+        #[arg(value_name = "VID:PID", value_parser = get_usb_id, default_value = "258a:005e")]
+        id: (u16, u16),
+
+        /// Interface the color settings are sent to.
+        #[arg(short, long, default_value_t = 1)]
+        interface: i32,
+
+        /// File to write the layout to. Printed if not provided.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    #[cfg(target_family = "unix")]
+    /// Run a daemon that keeps the keyboard open.
+    ///
+    /// While the daemon is running, other commands send their settings to it instead of opening
+    /// the keyboard themselves.
+    Daemon {
+        /// Socket to listen on. Defaults to $KLUDGED_SOCKET, or $XDG_RUNTIME_DIR/kludged.sock.
+        ///
+        /// Other commands only look for the daemon at the default socket. To use another socket,
+        /// set KLUDGED_SOCKET for both the daemon, and the other commands instead.
+        #[arg(short, long)]
+        socket: Option<PathBuf>,
+
+        /// Stop the running daemon.
+        #[arg(long)]
+        stop: bool,
+    },
 }
@@ -0,0 +1,181 @@
+use std::io::{BufRead, Write};
+
+use anyhow::bail;
+use hidapi::{DeviceInfo, HidApi};
+use palette::Srgb;
+use strum::IntoEnumIterator;
+
+use crate::keyboards::{
+    layout::{KeyIndexes, KeyTable},
+    on_interface,
+    rk68::{color_slots, keys::Keys, Rk68},
+    transport::Transport,
+    KeyboardColorable,
+};
+
+const HELP: &str = "\
+One color slot of the keyboard is lit at a time. Type the name of the key that lit up, in
+kebab-case such as \"left-shift\", and press enter.
+
+  (empty)  Use the name in brackets, if any. Same as \"-\" otherwise.
+  -        No key lit up.
+  <        Go back to the previous slot.
+  .        Stop, and write out the keys named so far.
+";
+
+const LIT: Srgb<u8> = Srgb::new(255, 255, 255);
+const OFF: Srgb<u8> = Srgb::new(0, 0, 0);
+
+/// Find a keyboard that uses the same color protocol as the RK68, but is not necessarily one.
+pub fn find_device(vid: u16, pid: u16, interface: i32) -> anyhow::Result<DeviceInfo> {
+    let mut hid_api = HidApi::new()?;
+    hid_api.reset_devices()?;
+    hid_api.add_devices(vid, pid)?;
+
+    let device_info = hid_api.device_list().find(|inf| {
+        on_interface(inf, interface)
+            && inf.usage() == <Rk68 as KeyboardColorable>::USAGE
+            && inf.usage_page() == <Rk68 as KeyboardColorable>::USAGE_PAGE
+    });
+
+    match device_info {
+        Some(device_info) => Ok(device_info.clone()),
+        None => {
+            bail!("No device with the ID {vid:04x}:{pid:04x} was found on interface {interface}.")
+        }
+    }
+}
+
+/// Light each color slot of the keyboard in turn, and ask which key lit up.
+///
+/// Answers are read from `input`, one per line, and the prompts are written to `output`. The
+/// session ends once every slot is named, `.` is answered, or `input` runs out. The names of the
+/// RK68's keys are offered for the slots the RK68 uses, which speeds up probing similar boards.
+pub fn probe<T, R, W>(mut kb: Rk68<T>, mut input: R, mut output: W) -> anyhow::Result<KeyTable>
+where
+    T: Transport,
+    R: BufRead,
+    W: Write,
+{
+    let slots = color_slots();
+    let mut names: Vec<Option<String>> = Vec::with_capacity(slots.len());
+
+    writeln!(output, "{HELP}")?;
+
+    while let Some(slot) = slots.get(names.len()) {
+        // Slots that no RK68 key uses are not cleared by setting the color of every key.
+        kb = slots
+            .iter()
+            .fold(kb, |kb, slot| kb.set_indexes_color(slot, OFF))
+            .set_indexes_color(slot, LIT)
+            .apply_color()?;
+
+        let suggestion = Keys::iter()
+            .find(|key| key.indexes() == *slot)
+            .map(<&str>::from);
+
+        write!(output, "Slot {}/{}", names.len() + 1, slots.len())?;
+        if let Some(suggestion) = suggestion {
+            write!(output, " [{suggestion}]")?;
+        }
+        write!(output, ": ")?;
+        output.flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            writeln!(output)?;
+            break;
+        }
+
+        let name = match answer.trim().to_ascii_lowercase().as_str() {
+            "." => break,
+            "<" => {
+                names.pop();
+                continue;
+            }
+            "-" => None,
+            "" => suggestion.map(str::to_owned),
+            name => Some(name.to_owned()),
+        };
+
+        if let Some(name) = &name {
+            if !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            {
+                writeln!(
+                    output,
+                    "Key names may only contain letters, numbers, and dashes."
+                )?;
+                continue;
+            }
+
+            if let Some(other) = names.iter().position(|other| other.as_ref() == Some(name)) {
+                writeln!(
+                    output,
+                    "{name} is already slot {}, use < to go back to it.",
+                    other + 1
+                )?;
+                continue;
+            }
+        }
+
+        names.push(name);
+    }
+
+    kb.set_color(OFF).apply_color()?;
+
+    let keys = names
+        .into_iter()
+        .zip(slots)
+        .filter_map(|(name, indexes)| {
+            Some(KeyIndexes {
+                key: name?,
+                indexes,
+            })
+        })
+        .collect();
+
+    Ok(KeyTable { keys })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::keyboards::{
+        rk68::{keys::Keys, Rk68},
+        transport::DryRunTransport,
+    };
+
+    use super::probe;
+
+    #[test]
+    fn test_probe() {
+        let transport = DryRunTransport::new();
+        let input = Cursor::new("Logo\n\n<\nesc\nesc\n-\nq\n.\n");
+        let mut output = vec![];
+
+        let table = probe(Rk68::with_transport(transport.clone()), input, &mut output).unwrap();
+        let names: Vec<_> = table.keys.iter().map(|key| key.key.as_str()).collect();
+        assert_eq!(names, ["logo", "esc", "q"]);
+        assert_eq!(table.keys[1].indexes, Keys::Esc.indexes());
+
+        // Names that are already used are asked for again.
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Slot 3/143 [tab]: esc is already slot 2"));
+
+        // Only the first slot is lit while it is asked for.
+        let reports = transport.reports();
+        let lit: Vec<_> = reports[..7]
+            .iter()
+            .enumerate()
+            .flat_map(|(step, report)| {
+                (6..report.len())
+                    .filter(|index| report[*index] == 255)
+                    .map(move |index| (step, index))
+            })
+            .collect();
+        assert_eq!(lit, [(0, 6), (0, 7), (0, 8)]);
+    }
+}
@@ -5,26 +5,36 @@ use clap::{
     error::ErrorKind,
     Arg, ArgAction, Args, FromArgMatches,
 };
+use palette::Srgb;
 use strum::VariantNames;
 
 use crate::{
-    cli::commons::color_arg,
-    keyboards::rk68::{Animation, AnimationOptions, Brightness, ColorOptions, Sleep, Speed},
+    cli::commons::{color_arg, get_color, get_stop},
+    keyboards::{
+        gradient::{Gradient, GradientFill, Interpolation},
+        rk68::{
+            keys::KeySelection, Animation, AnimationOptions, Brightness, ColorOptions, Sleep, Speed,
+        },
+    },
     possible_values,
 };
 
+use super::{get_key_color, get_keys, GradientColors, KeyColors};
+
+fn sleep_arg() -> Arg {
+    Arg::new("sleep")
+        .short('s')
+        .long("sleep")
+        .default_value(<Sleep as Into<&'static str>>::into(Sleep::default()))
+        .value_parser(PossibleValuesParser::new(Sleep::VARIANTS))
+}
+
 impl Args for ColorOptions {
     fn augment_args(cmd: clap::Command) -> clap::Command {
-        cmd.arg(
-            Arg::new("sleep")
-                .short('s')
-                .long("sleep")
-                .default_value(<Sleep as Into<&'static str>>::into(Sleep::default()))
-                .value_parser(PossibleValuesParser::new(Sleep::VARIANTS)),
-        )
-        .arg(color_arg().required(true))
-        .about("Set the color of the keyboard.")
-        .long_about("Set the color of the keyboard. Some keyboards may accept extra arguments.")
+        cmd.arg(sleep_arg())
+            .arg(color_arg().required(true))
+            .about("Set the color of the keyboard.")
+            .long_about("Set the color of the keyboard. Some keyboards may accept extra arguments.")
     }
 
     fn augment_args_for_update(cmd: clap::Command) -> clap::Command {
@@ -106,3 +116,149 @@ impl FromArgMatches for AnimationOptions {
         Ok(())
     }
 }
+
+impl Args for KeyColors {
+    fn augment_args(cmd: clap::Command) -> clap::Command {
+        cmd.args([
+            Arg::new("key")
+                .short('k')
+                .long("key")
+                .value_name("KEYS=COLOR")
+                .value_parser(get_key_color)
+                .action(ArgAction::Append)
+                .required(true)
+                .help("Set the color of keys, can be provided multiple times.")
+                .long_help(
+                    "Set the color of keys, such as \"esc=red\", or \"left-shift=#00ff00\". \
+                    Can be provided multiple times, later values override earlier ones.\n\n\
+                    Keys can be selected with a comma separated list of key names, key groups \
+                    (such as \"alphas\", \"wasd\", or \"home-row\"), and ranges along a row \
+                    (such as \"q..p\"). Prefixing any of these with \"-\" removes the keys \
+                    instead, for example \"alphas,-wasd\".",
+                ),
+            Arg::new("default")
+                .short('d')
+                .long("default")
+                .value_name("COLOR")
+                .value_parser(get_color)
+                .default_value("black")
+                .help("Color of the keys that were not provided."),
+            sleep_arg(),
+        ])
+        .about("Set the color of individual keys.")
+    }
+
+    fn augment_args_for_update(cmd: clap::Command) -> clap::Command {
+        Self::augment_args(cmd)
+    }
+}
+
+impl FromArgMatches for KeyColors {
+    fn from_arg_matches(matches: &clap::ArgMatches) -> Result<Self, clap::Error> {
+        Ok(KeyColors {
+            keys: matches
+                .get_many("key")
+                .map(|keys| keys.cloned().collect())
+                .unwrap_or_default(),
+            default: *matches.get_one("default").unwrap(),
+            options: ColorOptions::from_arg_matches(matches)?,
+        })
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &clap::ArgMatches) -> Result<(), clap::Error> {
+        *self = Self::from_arg_matches(matches)?;
+
+        Ok(())
+    }
+}
+
+impl Args for GradientColors {
+    fn augment_args(cmd: clap::Command) -> clap::Command {
+        cmd.args([
+            Arg::new("stops")
+                .long("stops")
+                .value_name("COLORS")
+                .value_parser(get_stop)
+                .value_delimiter(',')
+                .num_args(1..)
+                .required(true)
+                .help("Colors of the gradient, separated by commas.")
+                .long_help(
+                    "Colors of the gradient, separated by commas. Each color can be followed by \
+                    a position from 0 to 1, or 0% to 100%, such as \"red@0.2\". Colors without \
+                    a position are spread evenly.",
+                ),
+            Arg::new("angle")
+                .short('a')
+                .long("angle")
+                .value_parser(clap::value_parser!(f32))
+                .default_value("0")
+                .allow_negative_numbers(true)
+                .help("Angle of the gradient in degrees, counter-clockwise from left to right."),
+            Arg::new("radial")
+                .short('r')
+                .long("radial")
+                .value_name("KEY")
+                .value_parser(get_keys)
+                .conflicts_with("angle")
+                .help("Spread the gradient outwards from a key, instead of along a line."),
+            Arg::new("interpolation")
+                .short('i')
+                .long("interpolation")
+                .default_value(<Interpolation as Into<&'static str>>::into(
+                    Interpolation::default(),
+                ))
+                .value_parser(possible_values!(Interpolation))
+                .help("Color space the colors are mixed in."),
+            sleep_arg(),
+        ])
+        .about("Paint a gradient over the keyboard.")
+    }
+
+    fn augment_args_for_update(cmd: clap::Command) -> clap::Command {
+        Self::augment_args(cmd)
+    }
+}
+
+impl FromArgMatches for GradientColors {
+    fn from_arg_matches(matches: &clap::ArgMatches) -> Result<Self, clap::Error> {
+        // Ok to unwrap, the stops are required.
+        let stops: Vec<(Option<f32>, Srgb<u8>)> =
+            matches.get_many("stops").unwrap().copied().collect();
+        let last = stops.len().saturating_sub(1).max(1) as f32;
+
+        let gradient = Gradient::with_stops(
+            stops
+                .into_iter()
+                .enumerate()
+                .map(|(i, (position, color))| (position.unwrap_or(i as f32 / last), color)),
+        )
+        .interpolation(*matches.get_one("interpolation").unwrap());
+
+        let fill = match matches.get_one::<KeySelection>("radial") {
+            Some(keys) => {
+                // Use the center of all of the selected keys, this way a selection such as
+                // "wasd" starts from the middle of the keys.
+                let centers: Vec<_> = keys.keys().iter().map(|k| k.geometry().center()).collect();
+                let len = centers.len() as f32;
+                let center = centers
+                    .into_iter()
+                    .fold((0.0, 0.0), |(x, y), (cx, cy)| (x + cx / len, y + cy / len));
+
+                GradientFill::radial(gradient, center)
+            }
+            None => GradientFill::linear(gradient, *matches.get_one("angle").unwrap()),
+        };
+
+        Ok(GradientColors {
+            fill,
+            options: ColorOptions::from_arg_matches(matches)?,
+        })
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &clap::ArgMatches) -> Result<(), clap::Error> {
+        *self = Self::from_arg_matches(matches)?;
+
+        Ok(())
+    }
+}
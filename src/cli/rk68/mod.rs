@@ -1,12 +1,86 @@
 pub mod impls;
 
-use crate::keyboards::{
-    rk68::{Animation, AnimationOptions, ColorOptions, Rk68},
-    Keyboard, KeyboardAnimatable, KeyboardAnimationOption, KeyboardColorOption, KeyboardColorable,
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
-use clap::{ArgMatches, Args, Command, FromArgMatches};
+use crate::{
+    cli::{
+        commons::{did_you_mean, get_color},
+        errors::{InvalidHex, InvalidKey, InvalidKeyColor},
+    },
+    errors::InvalidSelection,
+    keyboards::{
+        capture::{read_reports, Capture, CaptureTransport},
+        gradient::GradientFill,
+        policy::WritePolicy,
+        rk68::{
+            decode::decode,
+            dump::Packet,
+            keys::{KeyGroup, KeySelection, Keys},
+            profile::{Profile, Source},
+            session::Rk68Session,
+            AnimationOptions, ColorOptions, Rk68,
+        },
+        selector::Target,
+        transport::{DryRunTransport, Transport},
+        Keyboard,
+    },
+    profiles::{ProfileStore, LAST_APPLIED},
+};
+
+#[cfg(target_family = "unix")]
+use crate::daemon::Client;
+
+use anyhow::bail;
+use clap::{Arg, ArgMatches, Args, Command, FromArgMatches};
+use color_print::{cformat, cstr};
 use palette::Srgb;
+use strum::{IntoEnumIterator, VariantNames};
+
+/// Arguments for setting the color of individual keys.
+#[derive(Clone, Debug)]
+pub struct KeyColors {
+    /// Colors for specific keys, applied in order.
+    pub keys: Vec<(KeySelection, Srgb<u8>)>,
+
+    /// Color of the keys that were not provided.
+    pub default: Srgb<u8>,
+
+    pub options: ColorOptions,
+}
+
+/// Arguments for painting a gradient over the keyboard.
+#[derive(Clone, Debug)]
+pub struct GradientColors {
+    pub fill: GradientFill,
+    pub options: ColorOptions,
+}
+
+/// Parse a key selection such as `"alphas,-wasd"`, suggesting a similar key, or key group if an
+/// unknown name is used.
+pub fn get_keys(arg: &str) -> Result<KeySelection, InvalidKeyColor> {
+    KeySelection::from_str(arg).map_err(|err| match err {
+        InvalidSelection::UnknownKey(key) => {
+            let candidates = Keys::VARIANTS.iter().chain(KeyGroup::VARIANTS).copied();
+            let suggestion = did_you_mean(&key.to_ascii_lowercase(), candidates);
+
+            InvalidKey { key, suggestion }.into()
+        }
+        err => err.into(),
+    })
+}
+
+/// Parse a `KEYS=COLOR` assignment, where `KEYS` is a key selection.
+pub fn get_key_color(arg: &str) -> Result<(KeySelection, Srgb<u8>), InvalidKeyColor> {
+    let (keys, color) = arg.split_once('=').ok_or(InvalidKeyColor::MissingColor)?;
+
+    Ok((get_keys(keys)?, get_color(color.trim())?))
+}
 
 /// Construct keyboard subcommand(s).
 pub fn command(cmd: Command) -> Command {
@@ -18,40 +92,456 @@ pub fn command(cmd: Command) -> Command {
 
 /// Construct inner keyboard subcommand(s).
 pub fn single_kb_command() -> impl IntoIterator<Item = Command> {
+    setting_commands().into_iter().chain([
+        get_command(),
+        profile_command(),
+        check_partial_command(),
+    ])
+}
+
+/// Subcommands that change the lighting of the keyboard.
+fn setting_commands() -> [Command; 4] {
     [
         ColorOptions::augment_args(Command::new("set-color")),
         AnimationOptions::augment_args(Command::new("set-anim")),
+        KeyColors::augment_args(Command::new("set-keys")),
+        GradientColors::augment_args(Command::new("set-gradient")),
     ]
 }
 
-pub fn handle_args(arg_matches: &ArgMatches) -> anyhow::Result<()> {
-    // Ok to unwrap, subcommand is required.
-    match arg_matches.subcommand().unwrap() {
+fn profile_command() -> Command {
+    let name_arg = || Arg::new("name").value_name("NAME").required(true);
+
+    Command::new("profile")
+        .about("Save, and apply lighting profiles.")
+        .long_about(
+            "Save, and apply lighting profiles. Profiles are stored in \
+            $XDG_CONFIG_HOME/kludged/profiles.",
+        )
+        .subcommand_required(true)
+        .subcommands([
+            Command::new("save")
+                .about("Save a setting as a profile, without applying it.")
+                .arg(name_arg())
+                .subcommands(setting_commands())
+                .subcommand_required(true),
+            Command::new("apply")
+                .about("Apply a saved profile.")
+                .arg(name_arg()),
+            Command::new("list").about("List the saved profiles."),
+            Command::new("delete")
+                .about("Delete a saved profile.")
+                .arg(name_arg()),
+        ])
+}
+
+fn get_command() -> Command {
+    Command::new("get")
+        .about("Show the current setting of the keyboard.")
+        .long_about(
+            "Show the current setting of the keyboard, in the same format as a profile. If the \
+            keyboard does not answer, the last setting applied with kludged is shown instead.",
+        )
+}
+
+fn check_partial_command() -> Command {
+    Command::new("check-partial-updates")
+        .about("Check if the keyboard applies colors when only part of them are sent.")
+        .long_about(
+            "Check if the keyboard applies colors when only part of them are sent. Every key is \
+            turned off, then a few keys are colored by sending only the packet that holds them. \
+            You will be asked which keys lit up.",
+        )
+}
+
+/// Build the profile described by one of the subcommands from [`setting_commands`].
+fn profile(subcommand: (&str, &ArgMatches)) -> anyhow::Result<Profile> {
+    let profile = match subcommand {
         ("set-color", arg_matches) => {
             // Ok to unwrap as we require the argument.
             let color: &Srgb<u8> = arg_matches.get_one("color").unwrap();
 
-            let color_options = ColorOptions::from_arg_matches(arg_matches)?;
+            Profile::color(*color, ColorOptions::from_arg_matches(arg_matches)?)
+        }
+        ("set-anim", arg_matches) => Profile::Animation {
+            animation: *arg_matches.get_one("anim").unwrap(),
+            options: AnimationOptions::from_arg_matches(arg_matches)?,
+        },
+        ("set-keys", arg_matches) => KeyColors::from_arg_matches(arg_matches)?.into(),
+        ("set-gradient", arg_matches) => GradientColors::from_arg_matches(arg_matches)?.into(),
+        (name, _) => unreachable!("unknown setting subcommand {name}"),
+    };
+
+    Ok(profile)
+}
+
+pub fn handle_args(arg_matches: &ArgMatches) -> anyhow::Result<()> {
+    let context = Context::from_arg_matches(arg_matches)?;
+
+    // Ok to unwrap, subcommand is required.
+    let result = match arg_matches.subcommand().unwrap() {
+        ("profile", arg_matches) => handle_profile(arg_matches, &context),
+        ("check-partial-updates", _) if context.dry_run => {
+            bail!("--dry-run can not be used with check-partial-updates.")
+        }
+        ("check-partial-updates", _) => handle_check_partial(&context),
+        ("get", _) if context.dry_run => bail!("--dry-run can not be used with get."),
+        ("get", _) => handle_get(&context),
+        subcommand if context.dry_run => print_packets(&profile(subcommand)?, &context),
+        subcommand => apply(profile(subcommand)?, &context),
+    };
+
+    if let Some(capture) = &context.capture {
+        capture.finish()?;
+    }
+
+    result
+}
+
+/// Arguments shared by the commands that talk to the keyboard.
+struct Context {
+    target: Target,
+    write_policy: WritePolicy,
+    capture: Option<Capture>,
+    dry_run: bool,
+}
+
+impl Context {
+    fn from_arg_matches(arg_matches: &ArgMatches) -> anyhow::Result<Self> {
+        let capture = arg_matches
+            .get_one::<PathBuf>("capture")
+            .map(Capture::create)
+            .transpose()?;
+
+        Ok(Self {
+            target: Target::from_arg_matches(arg_matches)?,
+            write_policy: WritePolicy::from_arg_matches(arg_matches)?,
+            capture,
+            dry_run: arg_matches.get_flag("dry_run"),
+        })
+    }
+
+    /// Set up a keyboard with the write policy, and capture.
+    fn prepare<T: Transport>(&self, kb: Rk68<T>) -> Rk68<CaptureTransport<T>> {
+        kb.with_write_policy(self.write_policy)
+            .with_capture(self.capture.clone())
+    }
+}
+
+/// Print the packets that applying the profile sends, with what each of their bytes is for.
+fn print_packets(profile: &Profile, context: &Context) -> anyhow::Result<()> {
+    let transport = DryRunTransport::new();
+    profile.apply(context.prepare(Rk68::with_transport(transport.clone())))?;
+
+    for report in transport.reports() {
+        println!("{}", Packet::new(&report));
+    }
+
+    Ok(())
+}
+
+/// The keyboard to use for commands that work on a single keyboard.
+pub fn keyboard(target: &Target) -> anyhow::Result<Rk68> {
+    if *target == Target::All {
+        bail!("--all can only be used with commands that change the lighting.");
+    }
+
+    // Never empty, an error is returned instead.
+    Ok(Rk68::from_target(target)?.remove(0))
+}
+
+/// Apply a profile through the daemon if one is running, otherwise write it to the keyboard
+/// directly.
+///
+/// The daemon writes with the policy it was started with. It only holds a single keyboard, so it
+/// is not used once a device is selected, or every keyboard is targeted. Packets sent by the daemon
+/// can not be captured either, so it is not used with `--capture`.
+fn apply(profile: Profile, context: &Context) -> anyhow::Result<()> {
+    #[cfg(target_family = "unix")]
+    let applied = match daemon(context)? {
+        Some(mut client) => {
+            client.apply(profile.clone())?;
+            true
+        }
+        None => false,
+    };
+    #[cfg(not(target_family = "unix"))]
+    let applied = false;
+
+    if !applied {
+        for kb in Rk68::from_target(&context.target)? {
+            profile.apply(context.prepare(kb))?;
+        }
+    }
+
+    // The setting was applied, failing to record it should not be reported as an error.
+    if let Ok(store) = ProfileStore::recorded() {
+        let _ = store.save(LAST_APPLIED, &profile);
+    }
+
+    Ok(())
+}
+
+/// The daemon to send requests to instead of opening the keyboard, if one is running, and can be
+/// used with the context. See [`apply`] for when it is not.
+#[cfg(target_family = "unix")]
+fn daemon(context: &Context) -> anyhow::Result<Option<Client>> {
+    if context.target != Target::First || context.capture.is_some() {
+        return Ok(None);
+    }
+
+    Ok(Client::from_env()?)
+}
+
+/// Print the current setting of the keyboard.
+///
+/// While a daemon is running, the last setting it applied is shown instead of asking the keyboard.
+/// The keyboard is only asked if nothing was applied through the daemon yet.
+fn handle_get(context: &Context) -> anyhow::Result<()> {
+    #[cfg(target_family = "unix")]
+    let current = match daemon(context)? {
+        Some(mut client) => client.current()?,
+        None => None,
+    };
+    #[cfg(not(target_family = "unix"))]
+    let current: Option<Profile> = None;
+
+    if let Some(profile) = current {
+        print!("{}", toml::to_string_pretty(&profile)?);
+
+        return Ok(());
+    }
+
+    let kb = context.prepare(keyboard(&context.target)?);
+    let (profile, source) = Profile::read(&kb, ProfileStore::recorded)?;
+
+    if source == Source::Recorded {
+        eprintln!(
+            "{}",
+            cstr!("<yellow>The keyboard did not answer, showing the last setting applied with kludged.</yellow>")
+        );
+    }
+
+    print!("{}", toml::to_string_pretty(&profile)?);
+
+    Ok(())
+}
+
+fn handle_check_partial(context: &Context) -> anyhow::Result<()> {
+    let session = Rk68Session::open(context.prepare(keyboard(&context.target)?))?;
+    let (session, honored) = session.partial_updates_honored(Srgb::new(255, 0, 0), |keys| {
+        let names: Vec<&'static str> = keys.iter().map(|key| key.into()).collect();
+        println!("Only these keys should now be red: {}", names.join(", "));
+        print!("Are they, and no others lit? [y/N] ");
+
+        // Anything but a yes, including no answer at all, is read as a no.
+        let mut answer = String::new();
+        io::stdout().flush().is_ok()
+            && io::stdin().read_line(&mut answer).is_ok()
+            && answer.trim().eq_ignore_ascii_case("y")
+    })?;
+
+    if honored {
+        println!("Partial updates are honored by the firmware.");
+    } else {
+        println!("Partial updates are not honored, every packet has to be sent.");
+    }
+
+    session.close()?;
+
+    Ok(())
+}
 
-            Rk68::new()?
-                .set_color(*color)
-                .set_color_parameters(color_options)
-                .apply_color()?;
+/// Decode the reports in a capture, or hex file, and print the settings as profiles.
+pub fn handle_decode(path: &Path) -> anyhow::Result<()> {
+    let data = fs::read(path)?;
 
-            Ok(())
+    let reports = match read_reports(&data) {
+        Ok(reports) => reports,
+        // Anything that is not a capture is read as text.
+        Err(err) => match String::from_utf8(data) {
+            Ok(text) => parse_hex(&text)?,
+            Err(_) => Err(err)?,
+        },
+    };
+
+    let decoding = decode(&reports);
+
+    for (i, decoded) in decoding.settings.iter().enumerate() {
+        if i > 0 {
+            println!();
         }
-        ("set-anim", arg_matches) => {
-            let animation: Animation = *arg_matches.get_one("anim").unwrap();
 
-            let anim_options = AnimationOptions::from_arg_matches(arg_matches)?;
+        println!(
+            "# Reports {} to {}",
+            decoded.reports.start + 1,
+            decoded.reports.end
+        );
+        print!("{}", toml::to_string_pretty(&decoded.profile)?);
+    }
+
+    for issue in &decoding.issues {
+        eprintln!("{}", cformat!("<yellow>{issue}</yellow>"));
+    }
 
-            Rk68::new()?
-                .set_animation(animation)
-                .set_animation_parameters(anim_options)
-                .apply_animation()?;
+    Ok(())
+}
+
+/// Read one report per line, written as hex bytes. Empty lines, and anything after a `#` are
+/// ignored.
+fn parse_hex(text: &str) -> Result<Vec<Vec<u8>>, InvalidHex> {
+    let mut reports = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let digits: String = line.split_whitespace().collect();
+        if digits.is_empty() {
+            continue;
+        }
 
-            Ok(())
+        let invalid = || InvalidHex { line: i + 1 };
+        if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+            return Err(invalid());
         }
-        _ => todo!(),
+
+        let report = (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+
+        reports.push(report);
+    }
+
+    Ok(reports)
+}
+
+fn handle_profile(arg_matches: &ArgMatches, context: &Context) -> anyhow::Result<()> {
+    let store = ProfileStore::from_env()?;
+
+    // Ok to unwrap, subcommand is required.
+    match arg_matches.subcommand().unwrap() {
+        ("save", arg_matches) => {
+            let name: &String = arg_matches.get_one("name").unwrap();
+            let profile = profile(arg_matches.subcommand().unwrap())?;
+
+            store.save(name, &profile)?;
+        }
+        ("apply", arg_matches) => {
+            let name: &String = arg_matches.get_one("name").unwrap();
+
+            let profile = store.load(name)?;
+
+            if context.dry_run {
+                print_packets(&profile, context)?;
+            } else {
+                apply(profile, context)?;
+            }
+        }
+        ("list", _) => {
+            for name in store.list()? {
+                println!("{name}");
+            }
+        }
+        ("delete", arg_matches) => {
+            let name: &String = arg_matches.get_one("name").unwrap();
+
+            store.delete(name)?;
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+impl From<KeyColors> for Profile {
+    fn from(value: KeyColors) -> Self {
+        let mut colors: BTreeMap<Keys, Srgb<u8>> =
+            Keys::iter().map(|key| (key, value.default)).collect();
+
+        for (keys, color) in value.keys {
+            colors.extend(keys.into_iter().map(|key| (key, color)));
+        }
+
+        Profile::colors(colors, value.options)
+    }
+}
+
+impl From<GradientColors> for Profile {
+    fn from(value: GradientColors) -> Self {
+        Profile::colors(value.fill.colors(&Keys::layout()), value.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{Args, Command, FromArgMatches};
+    use palette::Srgb;
+
+    use crate::{
+        cli::errors::InvalidKeyColor,
+        keyboards::rk68::{keys::Keys, profile::Profile},
+    };
+
+    use super::{get_key_color, KeyColors};
+
+    #[test]
+    fn test_get_key_color() {
+        let (keys, color) = get_key_color("esc=red").unwrap();
+        assert_eq!(keys.keys(), [Keys::Esc]);
+        assert_eq!(color, Srgb::new(255, 0, 0));
+
+        let (keys, color) = get_key_color("wasd=#00ff00").unwrap();
+        assert_eq!(keys.keys(), [Keys::W, Keys::A, Keys::S, Keys::D]);
+        assert_eq!(color, Srgb::new(0, 255, 0));
+
+        let (keys, _) = get_key_color(" Esc, tab = blue").unwrap();
+        assert_eq!(keys.keys(), [Keys::Esc, Keys::Tab]);
+    }
+
+    #[test]
+    fn test_invalid_key_color() {
+        assert!(matches!(
+            get_key_color("esc"),
+            Err(InvalidKeyColor::MissingColor)
+        ));
+        assert!(matches!(
+            get_key_color("esc=nocolor"),
+            Err(InvalidKeyColor::InvalidColor(_))
+        ));
+
+        let Err(InvalidKeyColor::InvalidKey(err)) = get_key_color("escc=red") else {
+            panic!("expected an unknown key");
+        };
+        assert_eq!(err.key, "escc");
+        assert_eq!(err.suggestion, Some("esc"));
+
+        let Err(InvalidKeyColor::InvalidKey(err)) = get_key_color("wsad=red") else {
+            panic!("expected an unknown key");
+        };
+        assert_eq!(err.suggestion, Some("wasd"));
+    }
+
+    #[test]
+    fn test_key_colors() {
+        let cmd = KeyColors::augment_args(Command::new("set-keys"));
+        let matches = cmd
+            .try_get_matches_from([
+                "set-keys",
+                "--key",
+                "wasd=#00ff00",
+                "-k",
+                "w=red",
+                "-d",
+                "blue",
+            ])
+            .unwrap();
+
+        let Profile::Static { colors, .. } = KeyColors::from_arg_matches(&matches).unwrap().into()
+        else {
+            panic!("expected a static profile");
+        };
+        assert_eq!(colors[&Keys::W], Srgb::new(255, 0, 0));
+        assert_eq!(colors[&Keys::A], Srgb::new(0, 255, 0));
+        assert_eq!(colors[&Keys::Esc], Srgb::new(0, 0, 255));
     }
 }
@@ -0,0 +1,362 @@
+use std::{
+    env,
+    fmt::Debug,
+    fs,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    ops::ControlFlow,
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{DaemonError, SessionError},
+    keyboards::{
+        rk68::{profile::Profile, session::Rk68Session, Rk68},
+        transport::{HidTransport, Transport},
+    },
+};
+
+/// Name of the socket the daemon listens on.
+const SOCKET_NAME: &str = "kludged.sock";
+
+/// Environment variable for the socket used by both the daemon, and its clients.
+pub const SOCKET_ENV: &str = "KLUDGED_SOCKET";
+
+/// How long the daemon waits on a client before dropping the connection.
+///
+/// The daemon handles one client at a time, so an idle client would block everyone else.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The socket a daemon listens on by default.
+///
+/// This is `$KLUDGED_SOCKET` if set, otherwise `$XDG_RUNTIME_DIR/kludged.sock`.
+pub fn socket_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(SOCKET_ENV).filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+
+    env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join(SOCKET_NAME))
+}
+
+/// A request sent to the daemon.
+///
+/// Requests, and responses are sent as a single line of JSON each.
+///
+/// ```json
+/// {"request":"apply","profile":{"mode":"animation","animation":"neon-stream"}}
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "kebab-case")]
+pub enum Request {
+    /// Check if the daemon is alive.
+    Ping,
+
+    /// Write a profile to the keyboard.
+    Apply { profile: Profile },
+
+    /// Get the last profile that was applied.
+    Current,
+
+    /// Stop the daemon.
+    Shutdown,
+}
+
+/// The response to a [`Request`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "kebab-case")]
+pub enum Response {
+    Ok,
+    Current { profile: Option<Profile> },
+    Error { message: String },
+}
+
+/// Keeps a keyboard open, along with its lighting state, and applies requests sent over a Unix
+/// socket.
+///
+/// The keyboard is only looked up once when the daemon starts, so clients do not have to
+/// enumerate the HID devices on every change. If the keyboard is disconnected, it is opened again
+/// on the next request.
+#[derive(Debug)]
+pub struct Daemon<T: Transport + Clone + Debug = HidTransport> {
+    kb: Rk68<T>,
+    session: Option<Rk68Session<T>>,
+    profile: Option<Profile>,
+}
+
+impl<T: Transport + Clone + Debug> Daemon<T> {
+    #[inline]
+    pub fn new(kb: Rk68<T>) -> Self {
+        Self {
+            kb,
+            session: None,
+            profile: None,
+        }
+    }
+
+    /// The last profile that was applied.
+    #[inline(always)]
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Bind to `path`, and serve requests until a [`Request::Shutdown`] is received.
+    ///
+    /// The socket is removed once the daemon stops.
+    pub fn run<P: AsRef<Path>>(self, path: P) -> Result<(), DaemonError> {
+        let path = path.as_ref();
+        let listener = bind(path)?;
+
+        let result = self.serve(&listener);
+        fs::remove_file(path)?;
+
+        result
+    }
+
+    /// Serve requests from an already bound listener until a [`Request::Shutdown`] is received.
+    pub fn serve(mut self, listener: &UnixListener) -> Result<(), DaemonError> {
+        for stream in listener.incoming() {
+            // A misbehaving client should not take the daemon down with it, so errors are only
+            // returned to the client where possible.
+            if let Ok(ControlFlow::Break(())) = self.handle_stream(stream?) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle a single request.
+    pub fn handle(&mut self, request: Request) -> Response {
+        match request {
+            Request::Ping | Request::Shutdown => Response::Ok,
+            Request::Apply { profile } => match self.apply(&profile) {
+                Ok(()) => {
+                    self.profile = Some(profile);
+
+                    Response::Ok
+                }
+                Err(err) => Response::Error {
+                    message: err.to_string(),
+                },
+            },
+            Request::Current => Response::Current {
+                profile: self.profile.clone(),
+            },
+        }
+    }
+
+    /// Apply a profile through the open session, reopening the keyboard once if it was
+    /// disconnected since the last request.
+    fn apply(&mut self, profile: &Profile) -> Result<(), SessionError> {
+        let session = match self.session.take() {
+            Some(session) => session,
+            None => Rk68Session::open(self.kb.clone())?,
+        };
+
+        let session = match profile.apply(session).map_err(SessionError::from) {
+            Err(err) if err.is_disconnected() => {
+                profile.apply(Rk68Session::open(self.kb.clone())?)?
+            }
+            result => result?,
+        };
+        self.session = Some(session);
+
+        Ok(())
+    }
+
+    fn handle_stream(&mut self, stream: UnixStream) -> Result<ControlFlow<()>, DaemonError> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+        let mut writer = stream.try_clone()?;
+
+        for line in BufReader::new(stream).lines() {
+            let (response, flow) = match serde_json::from_str(&line?) {
+                Ok(Request::Shutdown) => (Response::Ok, ControlFlow::Break(())),
+                Ok(request) => (self.handle(request), ControlFlow::Continue(())),
+                Err(err) => (
+                    Response::Error {
+                        message: err.to_string(),
+                    },
+                    ControlFlow::Continue(()),
+                ),
+            };
+
+            write_message(&mut writer, &response)?;
+
+            if flow.is_break() {
+                return Ok(flow);
+            }
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// Bind a listener to `path`, replacing the socket of a daemon that is no longer running.
+pub fn bind<P: AsRef<Path>>(path: P) -> Result<UnixListener, DaemonError> {
+    let path = path.as_ref();
+
+    match UnixListener::bind(path) {
+        Err(err) if err.kind() == ErrorKind::AddrInUse => {
+            // The socket is left behind if the daemon is killed. If nothing answers on it, it
+            // is safe to remove.
+            if UnixStream::connect(path).is_ok() {
+                return Err(DaemonError::AlreadyRunning(path.to_path_buf()));
+            }
+
+            fs::remove_file(path)?;
+
+            Ok(UnixListener::bind(path)?)
+        }
+        listener => Ok(listener?),
+    }
+}
+
+fn write_message<W: Write, M: Serialize>(writer: &mut W, message: &M) -> Result<(), DaemonError> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+
+    writer.write_all(&line)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// A connection to a running [`Daemon`].
+#[derive(Debug)]
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    pub fn connect<P: AsRef<Path>>(path: P) -> Result<Self, DaemonError> {
+        let writer = UnixStream::connect(path)?;
+        let reader = BufReader::new(writer.try_clone()?);
+
+        Ok(Self { reader, writer })
+    }
+
+    /// Connect to the daemon listening on [`socket_path`].
+    ///
+    /// Returns [`None`] if no daemon is running.
+    pub fn from_env() -> Result<Option<Self>, DaemonError> {
+        let Some(path) = socket_path() else {
+            return Ok(None);
+        };
+
+        match Self::connect(path) {
+            Ok(client) => Ok(Some(client)),
+            Err(DaemonError::Io(err))
+                if matches!(
+                    err.kind(),
+                    ErrorKind::NotFound | ErrorKind::ConnectionRefused
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Send a request, and wait for the response.
+    ///
+    /// A [`Response::Error`] is returned as [`DaemonError::Remote`].
+    pub fn request(&mut self, request: &Request) -> Result<Response, DaemonError> {
+        write_message(&mut self.writer, request)?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+
+        match serde_json::from_str(&line)? {
+            Response::Error { message } => Err(DaemonError::Remote(message)),
+            response => Ok(response),
+        }
+    }
+
+    pub fn ping(&mut self) -> Result<(), DaemonError> {
+        self.expect_ok(&Request::Ping)
+    }
+
+    pub fn apply(&mut self, profile: Profile) -> Result<(), DaemonError> {
+        self.expect_ok(&Request::Apply { profile })
+    }
+
+    pub fn current(&mut self) -> Result<Option<Profile>, DaemonError> {
+        match self.request(&Request::Current)? {
+            Response::Current { profile } => Ok(profile),
+            _ => Err(DaemonError::UnexpectedResponse),
+        }
+    }
+
+    pub fn shutdown(&mut self) -> Result<(), DaemonError> {
+        self.expect_ok(&Request::Shutdown)
+    }
+
+    fn expect_ok(&mut self, request: &Request) -> Result<(), DaemonError> {
+        match self.request(request)? {
+            Response::Ok => Ok(()),
+            _ => Err(DaemonError::UnexpectedResponse),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process, thread};
+
+    use palette::Srgb;
+
+    use crate::keyboards::{
+        mock::MockTransport,
+        rk68::{profile::Profile, ColorOptions, Rk68},
+    };
+
+    use super::{bind, Client, Daemon};
+
+    #[test]
+    fn test_daemon() {
+        let path = env::temp_dir().join(format!("kludged-daemon-{}.sock", process::id()));
+        let transport = MockTransport::new();
+
+        let listener = bind(&path).unwrap();
+        let daemon = Daemon::new(Rk68::with_transport(transport.clone()));
+        let handle = thread::spawn(move || daemon.serve(&listener));
+
+        let profile = Profile::color(Srgb::new(255, 0, 0), ColorOptions::default());
+
+        let mut client = Client::connect(&path).unwrap();
+        client.ping().unwrap();
+        assert_eq!(client.current().unwrap(), None);
+        client.apply(profile.clone()).unwrap();
+        assert_eq!(client.current().unwrap(), Some(profile.clone()));
+        assert_eq!(transport.reports().len(), 7);
+
+        // The keyboard is reopened if it was unplugged in the meantime.
+        transport.disconnect();
+        assert!(client.apply(profile.clone()).is_err());
+        transport.reconnect();
+        client.apply(profile.clone()).unwrap();
+        client.apply(profile.clone()).unwrap();
+        assert_eq!(transport.open_count(), 2);
+
+        // A new connection sees the same state.
+        drop(client);
+        let mut client = Client::connect(&path).unwrap();
+        assert!(client.current().unwrap().is_some());
+
+        client.shutdown().unwrap();
+        handle.join().unwrap().unwrap();
+
+        // Nothing is listening anymore, so the stale socket can be replaced.
+        drop(bind(&path).unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf};
 
 use hidapi::HidError;
 use thiserror::Error;
@@ -19,3 +19,197 @@ impl Display for DeviceNotFound {
         write!(f, "The requested device could not be found.")
     }
 }
+
+/// An environment variable is set to a value that can not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+#[error("${name} is set to \"{value}\", which is not a valid value.")]
+pub struct InvalidEnvVar {
+    pub name: &'static str,
+    pub value: String,
+}
+
+/// An error from an open device session.
+///
+/// A [`HidError`] returned elsewhere, such as from
+/// [`KeyboardColorable::apply_color`](crate::keyboards::KeyboardColorable::apply_color) on a
+/// session, can be converted into this to check if the device was disconnected.
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("The keyboard was disconnected.")]
+    Disconnected(#[source] HidError),
+    #[error(transparent)]
+    HidApiError(HidError),
+}
+
+impl SessionError {
+    #[inline]
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self, Self::Disconnected(_))
+    }
+}
+
+impl From<HidError> for SessionError {
+    fn from(value: HidError) -> Self {
+        if is_disconnected(&value) {
+            Self::Disconnected(value)
+        } else {
+            Self::HidApiError(value)
+        }
+    }
+}
+
+/// Check if an error was caused by the device being disconnected.
+///
+/// Only errors saying the device is gone count. A "Broken pipe" is also returned when the device
+/// stalls, or rejects a transfer, so it is treated like any other failed write, and retried.
+pub fn is_disconnected(err: &HidError) -> bool {
+    // hidapi only reports errors from the OS as text, so the message is all there is to go on.
+    // These are ENODEV, and ENXIO ("No such device or address") on Unix systems, and
+    // ERROR_DEVICE_NOT_CONNECTED on Windows.
+    const DISCONNECTED: [&str; 2] = ["No such device", "not connected"];
+
+    let message = match err {
+        HidError::HidApiError { message } => message.clone(),
+        HidError::IoError { error } => error.to_string(),
+        _ => return false,
+    };
+
+    DISCONNECTED.iter().any(|msg| message.contains(msg))
+}
+
+impl From<SessionError> for HidError {
+    fn from(value: SessionError) -> Self {
+        match value {
+            SessionError::Disconnected(err) | SessionError::HidApiError(err) => err,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ReadError {
+    #[error(transparent)]
+    HidApiError(#[from] HidError),
+    #[error("The keyboard did not answer.")]
+    NoResponse,
+    #[error("The keyboard answered with a report that could not be understood: {0:02x?}")]
+    InvalidResponse(Vec<u8>),
+}
+
+#[derive(Clone, Debug, Error)]
+pub enum InvalidSelection {
+    #[error("Unknown key, or key group \"{0}\".")]
+    UnknownKey(String),
+    #[error("The keys in the range \"{0}\" are not on the same row.")]
+    RangeNotInRow(String),
+    #[error("The selection does not contain any keys.")]
+    Empty,
+}
+
+/// An error while reading the reports out of a capture.
+#[derive(Clone, Debug, Error)]
+pub enum CaptureError {
+    #[error("Not a pcap, or pcapng file, or the file is big endian which is not supported.")]
+    UnknownFormat,
+    #[error("Captures with link type {0} are not supported, only USB captures from usbmon, and USBPcap are.")]
+    UnsupportedLinkType(u32),
+    #[error("The capture ends in the middle of a block.")]
+    Truncated,
+}
+
+/// An error while loading a model definition, or changing the settings of a keyboard made from
+/// one.
+#[derive(Debug, Error)]
+pub enum DefinitionError {
+    #[error("The model definition is invalid: {0}")]
+    Invalid(String),
+    #[error("Unknown key \"{0}\".")]
+    UnknownKey(String),
+    #[error("Unknown option \"{0}\".")]
+    UnknownOption(String),
+    #[error("\"{value}\" is not a possible value of \"{option}\".")]
+    InvalidValue { option: String, value: String },
+    #[error("The model does not support animations.")]
+    NoAnimation,
+    #[error("Unable to find the configuration directory, set $XDG_CONFIG_HOME or $HOME.")]
+    NoConfigDir,
+    #[error("{}: {source}", path.display())]
+    File {
+        path: PathBuf,
+        source: Box<DefinitionError>,
+    },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "profiles")]
+    #[error(transparent)]
+    Deserialize(#[from] toml::de::Error),
+}
+
+#[cfg(feature = "profiles")]
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("Unable to find the configuration directory, set $XDG_CONFIG_HOME or $HOME.")]
+    NoConfigDir,
+    #[error("Unable to find the state directory, set $XDG_STATE_HOME or $HOME.")]
+    NoStateDir,
+    #[error("\"{0}\" is not a valid profile name.")]
+    InvalidName(String),
+    #[error("A profile named \"{0}\" does not exist.")]
+    NotFound(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serialize(#[from] toml::ser::Error),
+    #[error(transparent)]
+    Deserialize(#[from] toml::de::Error),
+}
+
+#[cfg(feature = "daemon")]
+#[derive(Debug, Error)]
+pub enum DaemonError {
+    #[error("Unable to find the runtime directory for the daemon socket, set $XDG_RUNTIME_DIR, or $KLUDGED_SOCKET.")]
+    NoRuntimeDir,
+    #[error("A daemon is already listening on \"{}\".", .0.display())]
+    AlreadyRunning(std::path::PathBuf),
+    #[error("The daemon failed to handle the request: {0}")]
+    Remote(String),
+    #[error("The daemon sent an unexpected response.")]
+    UnexpectedResponse,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use hidapi::HidError;
+
+    use super::is_disconnected;
+
+    fn api_error(message: &str) -> HidError {
+        HidError::HidApiError {
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_is_disconnected() {
+        // ENODEV, and ENXIO.
+        assert!(is_disconnected(&api_error("No such device")));
+        assert!(is_disconnected(&api_error("No such device or address")));
+        // ERROR_DEVICE_NOT_CONNECTED
+        assert!(is_disconnected(&api_error("The device is not connected.")));
+        #[cfg(target_os = "linux")]
+        assert!(is_disconnected(&HidError::IoError {
+            error: std::io::Error::from_raw_os_error(19),
+        }));
+
+        // EPIPE is returned for stalls, and rejected transfers as well, which are worth retrying.
+        assert!(!is_disconnected(&api_error("Broken pipe")));
+        assert!(!is_disconnected(&HidError::IoError {
+            error: std::io::ErrorKind::BrokenPipe.into(),
+        }));
+        assert!(!is_disconnected(&api_error("Input/output error")));
+        assert!(!is_disconnected(&api_error("Connection timed out")));
+    }
+}
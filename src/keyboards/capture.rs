@@ -0,0 +1,662 @@
+//! Capture the reports sent to, and read from a keyboard into a pcapng file.
+//!
+//! Each report is stored as a USB control transfer, in the format of the Linux usbmon capture
+//! interface. Wireshark shows them the same way as a capture of the keyboard taken with usbmon,
+//! so that the traffic of kludged can be compared against the official application.
+//!
+//! ```no_run
+//! # use kludged::keyboards::{capture::Capture, rk68::Rk68, Keyboard, KeyboardColorable};
+//! let capture = Capture::create("kludged.pcapng")?;
+//!
+//! Rk68::new()?.with_capture(Some(capture.clone())).apply_color()?;
+//! capture.finish()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hidapi::HidResult;
+
+use crate::errors::CaptureError;
+
+use super::transport::{Transport, TransportDevice};
+
+/// `LINKTYPE_USB_LINUX_MMAPPED`, USB packets with the 64 byte usbmon header.
+const LINKTYPE_USB_LINUX_MMAPPED: u16 = 220;
+
+/// `LINKTYPE_USB_LINUX`, USB packets with the older 48 byte usbmon header.
+const LINKTYPE_USB_LINUX: u16 = 189;
+
+/// `LINKTYPE_USBPCAP`, USB packets captured by USBPcap on Windows.
+const LINKTYPE_USBPCAP: u16 = 249;
+
+/// Largest packet stored, any bytes past this are cut off.
+const SNAPLEN: u32 = 0xFFFF;
+
+const SET_REPORT: u8 = 0x09;
+const GET_REPORT: u8 = 0x01;
+
+/// Report type of a feature report, as used in the high byte of `wValue`.
+const FEATURE_REPORT: u16 = 0x03;
+
+/// Status of a transfer that was submitted, but has not completed yet.
+const EINPROGRESS: i32 = -115;
+
+/// Status of a transfer that failed.
+const EPIPE: i32 = -32;
+
+/// A pcapng file that reports are written to.
+///
+/// Clones write to the same file, so a single capture can be shared by several keyboards. Failing
+/// to write to the capture never fails the write to the keyboard. Instead, the first error is kept
+/// and returned by [`Capture::finish`].
+#[derive(Clone)]
+pub struct Capture {
+    inner: Arc<Mutex<CaptureState>>,
+}
+
+struct CaptureState {
+    writer: Box<dyn Write + Send>,
+    next_id: u64,
+    error: Option<io::Error>,
+}
+
+/// A control transfer, as it is written to the capture.
+struct Transfer<'a> {
+    setup: [u8; 8],
+    is_in: bool,
+    data: &'a [u8],
+    status: i32,
+}
+
+impl Capture {
+    /// Create, or truncate the file at `path`, and start a capture in it.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+
+    /// Start a capture in any writer.
+    pub fn new<W: Write + Send + 'static>(mut writer: W) -> io::Result<Self> {
+        write_block(&mut writer, 0x0A0D_0D0A, |body| {
+            body.extend(0x1A2B_3C4Du32.to_le_bytes());
+            // Version 1.0.
+            body.extend(1u16.to_le_bytes());
+            body.extend(0u16.to_le_bytes());
+            // The length of the section is not known up front.
+            body.extend((-1i64).to_le_bytes());
+        })?;
+
+        write_block(&mut writer, 0x0000_0001, |body| {
+            body.extend(LINKTYPE_USB_LINUX_MMAPPED.to_le_bytes());
+            body.extend(0u16.to_le_bytes());
+            body.extend(SNAPLEN.to_le_bytes());
+        })?;
+
+        writer.flush()?;
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(CaptureState {
+                writer: Box::new(writer),
+                next_id: 0,
+                error: None,
+            })),
+        })
+    }
+
+    /// Flush the capture, and return the first error that happened while writing to it.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut state = self.state();
+
+        if let Some(err) = state.error.take() {
+            return Err(err);
+        }
+
+        state.writer.flush()
+    }
+
+    /// Record a submitted transfer, and its completion.
+    fn record(&self, submit: Transfer, complete: Transfer) {
+        let mut state = self.state();
+
+        let id = state.next_id;
+        state.next_id += 1;
+
+        let result = [(b'S', submit), (b'C', complete)]
+            .into_iter()
+            .try_for_each(|(kind, transfer)| write_transfer(&mut state.writer, id, kind, transfer))
+            .and_then(|_| state.writer.flush());
+
+        if let Err(err) = result {
+            state.error.get_or_insert(err);
+        }
+    }
+
+    #[inline(always)]
+    fn state(&self) -> MutexGuard<'_, CaptureState> {
+        self.inner.lock().unwrap()
+    }
+}
+
+impl Debug for Capture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Capture")
+            .field("next_id", &self.state().next_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Write a pcapng block, where `body` fills in everything between the lengths.
+fn write_block<W: Write>(
+    w: &mut W,
+    block_type: u32,
+    body: impl FnOnce(&mut Vec<u8>),
+) -> io::Result<()> {
+    let mut data = vec![];
+    body(&mut data);
+
+    // The body is padded to 32 bits.
+    data.resize(data.len().next_multiple_of(4), 0);
+
+    let len = (data.len() + 12) as u32;
+
+    w.write_all(&block_type.to_le_bytes())?;
+    w.write_all(&len.to_le_bytes())?;
+    w.write_all(&data)?;
+    w.write_all(&len.to_le_bytes())
+}
+
+/// Write a transfer as an enhanced packet block, with a usbmon header.
+fn write_transfer<W: Write>(w: &mut W, id: u64, kind: u8, transfer: Transfer) -> io::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let data = &transfer.data[..transfer.data.len().min(SNAPLEN as usize - 64)];
+
+    let mut packet = Vec::with_capacity(64 + data.len());
+    packet.extend(id.to_le_bytes());
+    packet.push(kind);
+    // Control transfer.
+    packet.push(2);
+    // Endpoint 0, with the direction in the top bit.
+    packet.push(if transfer.is_in { 0x80 } else { 0x00 });
+    // Device, and bus numbers are not known through hidapi.
+    packet.push(0);
+    packet.extend(0u16.to_le_bytes());
+    // The setup packet is only present on submission.
+    packet.push(if kind == b'S' { 0 } else { b'-' });
+    packet.push(if data.is_empty() {
+        if transfer.is_in {
+            b'<'
+        } else {
+            b'>'
+        }
+    } else {
+        0
+    });
+    packet.extend((now.as_secs() as i64).to_le_bytes());
+    packet.extend((now.subsec_micros() as i32).to_le_bytes());
+    packet.extend(transfer.status.to_le_bytes());
+    // The data is only captured in the direction it travels, the length is the one requested, or
+    // sent in the other.
+    let length = if (kind == b'S') == transfer.is_in {
+        u16::from_le_bytes([transfer.setup[6], transfer.setup[7]]) as u32
+    } else {
+        transfer.data.len() as u32
+    };
+    packet.extend(length.to_le_bytes());
+    packet.extend((data.len() as u32).to_le_bytes());
+    packet.extend(if kind == b'S' { transfer.setup } else { [0; 8] });
+    // Interval, start frame, transfer flags, and number of isochronous descriptors.
+    packet.extend([0; 16]);
+    packet.extend(data);
+
+    let micros = now.as_micros() as u64;
+
+    write_block(w, 0x0000_0006, |body| {
+        // Interface ID.
+        body.extend(0u32.to_le_bytes());
+        body.extend(((micros >> 32) as u32).to_le_bytes());
+        body.extend((micros as u32).to_le_bytes());
+        body.extend((packet.len() as u32).to_le_bytes());
+        body.extend((packet.len() as u32).to_le_bytes());
+        body.extend(&packet);
+    })
+}
+
+/// The setup packet of a `SET_REPORT`, or `GET_REPORT` request for a feature report.
+fn setup(request: u8, report_id: u8, interface: u16, len: usize) -> [u8; 8] {
+    // Class request to an interface, with the direction in the top bit.
+    let request_type = if request == GET_REPORT { 0xA1 } else { 0x21 };
+    let value = FEATURE_REPORT << 8 | u16::from(report_id);
+
+    let [value_low, value_high] = value.to_le_bytes();
+    let [index_low, index_high] = interface.to_le_bytes();
+    let [len_low, len_high] = (len as u16).to_le_bytes();
+
+    [
+        request_type,
+        request,
+        value_low,
+        value_high,
+        index_low,
+        index_high,
+        len_low,
+        len_high,
+    ]
+}
+
+/// Every feature report sent, or read back in a capture of USB traffic.
+///
+/// Reads pcapng files, such as the ones made by [`Capture`], and pcap files. The packets have to
+/// be captured with Linux usbmon, or USBPcap on Windows. Only the data of `SET_REPORT`, and
+/// `GET_REPORT` requests for feature reports is returned, in the order it was captured.
+pub fn read_reports(data: &[u8]) -> Result<Vec<Vec<u8>>, CaptureError> {
+    fn u32_at(data: &[u8], offset: usize) -> Result<u32, CaptureError> {
+        let bytes = data
+            .get(offset..offset + 4)
+            .ok_or(CaptureError::Truncated)?;
+
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    let mut reader = ReportReader::default();
+
+    match u32_at(data, 0).map_err(|_| CaptureError::UnknownFormat)? {
+        0x0A0D_0D0A => {
+            if u32_at(data, 8)? != 0x1A2B_3C4D {
+                return Err(CaptureError::UnknownFormat);
+            }
+
+            let mut link_types = vec![];
+            let mut rest = data;
+
+            while !rest.is_empty() {
+                let (block_type, len) = (u32_at(rest, 0)?, u32_at(rest, 4)? as usize);
+                if !(12..=rest.len()).contains(&len) {
+                    return Err(CaptureError::Truncated);
+                }
+                let body = &rest[8..len - 4];
+
+                match block_type {
+                    // Interface description.
+                    1 => link_types.push(u32_at(body, 0)? as u16),
+                    // Enhanced packet.
+                    6 => {
+                        let interface = u32_at(body, 0)? as usize;
+                        let captured = u32_at(body, 12)? as usize;
+                        let packet = body.get(20..20 + captured).ok_or(CaptureError::Truncated)?;
+
+                        // Packets from an interface that was never described are skipped.
+                        if let Some(link_type) = link_types.get(interface) {
+                            reader.read(*link_type, packet)?;
+                        }
+                    }
+                    // Simple packet, always from the first interface.
+                    3 => {
+                        let captured = (u32_at(body, 0)? as usize).min(body.len() - 4);
+
+                        if let Some(link_type) = link_types.first() {
+                            reader.read(*link_type, &body[4..4 + captured])?;
+                        }
+                    }
+                    _ => {}
+                }
+
+                rest = &rest[len..];
+            }
+        }
+        // Microsecond, and nanosecond timestamps.
+        0xA1B2_C3D4 | 0xA1B2_3C4D => {
+            let link_type = u32_at(data, 20)? as u16;
+            let mut rest = data.get(24..).ok_or(CaptureError::Truncated)?;
+
+            while !rest.is_empty() {
+                let captured = u32_at(rest, 8)? as usize;
+                let packet = rest.get(16..16 + captured).ok_or(CaptureError::Truncated)?;
+
+                reader.read(link_type, packet)?;
+                rest = &rest[16 + captured..];
+            }
+        }
+        _ => return Err(CaptureError::UnknownFormat),
+    }
+
+    Ok(reader.reports)
+}
+
+/// Follows the control transfers of a capture, and collects the feature reports in them.
+#[derive(Default)]
+struct ReportReader {
+    /// Requests that were submitted, but whose data has not been seen yet, by their ID.
+    pending: HashMap<u64, u8>,
+    reports: Vec<Vec<u8>>,
+}
+
+impl ReportReader {
+    fn read(&mut self, link_type: u16, packet: &[u8]) -> Result<(), CaptureError> {
+        match link_type {
+            LINKTYPE_USB_LINUX_MMAPPED => self.read_usbmon(packet, 64),
+            LINKTYPE_USB_LINUX => self.read_usbmon(packet, 48),
+            LINKTYPE_USBPCAP => self.read_usbpcap(packet),
+            link_type => return Err(CaptureError::UnsupportedLinkType(link_type.into())),
+        }
+
+        Ok(())
+    }
+
+    /// The request of a setup packet, if it is for a feature report.
+    fn feature_request(setup: &[u8]) -> Option<u8> {
+        let is_feature = setup.len() >= 8
+            && setup[0] & 0x7F == 0x21
+            && matches!(setup[1], SET_REPORT | GET_REPORT)
+            && u16::from(setup[3]) == FEATURE_REPORT;
+
+        is_feature.then_some(setup[1])
+    }
+
+    fn read_usbmon(&mut self, packet: &[u8], header_len: usize) {
+        // Only control transfers carry feature reports.
+        if packet.len() < header_len || packet[9] != 2 {
+            return;
+        }
+
+        let id = u64::from_le_bytes(packet[..8].try_into().unwrap());
+        let data = &packet[header_len..];
+
+        match packet[8] {
+            b'S' if packet[14] == 0 => match Self::feature_request(&packet[40..48]) {
+                Some(SET_REPORT) if !data.is_empty() => self.reports.push(data.to_vec()),
+                Some(GET_REPORT) => {
+                    self.pending.insert(id, GET_REPORT);
+                }
+                _ => {}
+            },
+            b'C' if self.pending.remove(&id).is_some() && !data.is_empty() => {
+                self.reports.push(data.to_vec());
+            }
+            _ => {}
+        }
+    }
+
+    fn read_usbpcap(&mut self, packet: &[u8]) {
+        let Some(header_len) = packet
+            .get(..2)
+            .map(|len| u16::from_le_bytes([len[0], len[1]]))
+        else {
+            return;
+        };
+        let header_len = usize::from(header_len);
+
+        // Control transfers have a longer header, ending with the stage.
+        if header_len < 28 || packet.len() < header_len || packet[22] != 2 {
+            return;
+        }
+
+        let id = u64::from_le_bytes(packet[2..10].try_into().unwrap());
+        let from_device = packet[16] & 1 == 1;
+        let data = &packet[header_len..];
+
+        match (packet[27], from_device) {
+            // Setup stage, the data of an OUT transfer may follow the setup packet.
+            (0, false) => match Self::feature_request(data) {
+                Some(SET_REPORT) if data.len() > 8 => self.reports.push(data[8..].to_vec()),
+                Some(request) => {
+                    self.pending.insert(id, request);
+                }
+                None => {}
+            },
+            // Data stage, or completion.
+            (1 | 3, _) if !data.is_empty() => {
+                let expected = if from_device { GET_REPORT } else { SET_REPORT };
+
+                if self.pending.get(&id) == Some(&expected) {
+                    self.pending.remove(&id);
+                    self.reports.push(data.to_vec());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A [`Transport`] that records every report to a [`Capture`], before passing it on.
+///
+/// Without a capture, reports are passed on as is.
+#[derive(Clone, Debug)]
+pub struct CaptureTransport<T: Transport> {
+    inner: T,
+    capture: Option<Capture>,
+    interface: u16,
+}
+
+impl<T: Transport> CaptureTransport<T> {
+    /// Capture the reports of `inner`. `interface` is the interface number the reports are sent
+    /// to, and is only used to fill in the capture.
+    #[inline]
+    pub fn new(inner: T, capture: Option<Capture>, interface: u16) -> Self {
+        Self {
+            inner,
+            capture,
+            interface,
+        }
+    }
+
+    /// The transport the reports are passed on to.
+    #[inline(always)]
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Transport> Transport for CaptureTransport<T> {
+    type Device = CaptureDevice<T::Device>;
+
+    fn open(&self) -> HidResult<Self::Device> {
+        Ok(CaptureDevice {
+            inner: self.inner.open()?,
+            capture: self.capture.clone(),
+            interface: self.interface,
+        })
+    }
+}
+
+/// A device opened through a [`CaptureTransport`].
+pub struct CaptureDevice<D: TransportDevice> {
+    inner: D,
+    capture: Option<Capture>,
+    interface: u16,
+}
+
+impl<D: TransportDevice> TransportDevice for CaptureDevice<D> {
+    fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+        let result = self.inner.send_feature_report(data);
+
+        if let Some(capture) = &self.capture {
+            let setup = setup(SET_REPORT, data[0], self.interface, data.len());
+
+            capture.record(
+                Transfer {
+                    setup,
+                    is_in: false,
+                    data,
+                    status: EINPROGRESS,
+                },
+                Transfer {
+                    setup,
+                    is_in: false,
+                    data: &[],
+                    status: if result.is_ok() { 0 } else { EPIPE },
+                },
+            );
+        }
+
+        result
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+        let report_id = buf[0];
+        let result = self.inner.get_feature_report(buf);
+
+        if let Some(capture) = &self.capture {
+            let setup = setup(GET_REPORT, report_id, self.interface, buf.len());
+            let (data, status) = match result {
+                Ok(len) => (&buf[..len], 0),
+                Err(_) => (&[][..], EPIPE),
+            };
+
+            capture.record(
+                Transfer {
+                    setup,
+                    is_in: true,
+                    data: &[],
+                    status: EINPROGRESS,
+                },
+                Transfer {
+                    setup,
+                    is_in: true,
+                    data,
+                    status,
+                },
+            );
+        }
+
+        result
+    }
+
+    fn close(self) -> HidResult<()> {
+        self.inner.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use crate::keyboards::{
+        mock::MockTransport, rk68::Rk68, KeyboardAnimatable, KeyboardColorable, KeyboardReadable,
+    };
+
+    use super::{read_reports, Capture, CaptureError};
+
+    /// A writer that can be read back from after being handed to a capture.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Split a pcapng file into its block types, and bodies.
+    fn blocks(mut data: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        let mut blocks = vec![];
+
+        while !data.is_empty() {
+            let block_type = u32::from_le_bytes(data[..4].try_into().unwrap());
+            let len = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+
+            assert_eq!(data[len - 4..len], data[4..8]);
+            blocks.push((block_type, data[8..len - 4].to_vec()));
+            data = &data[len..];
+        }
+
+        blocks
+    }
+
+    #[test]
+    fn test_read_reports() {
+        let out = Shared::default();
+        let capture = Capture::new(out.clone()).unwrap();
+
+        let mock = MockTransport::new();
+        mock.push_response([0x0A, 0x01, 0x01, 0x02, 0x29, 0x12]);
+
+        let kb = Rk68::with_transport(mock.clone()).with_capture(Some(capture.clone()));
+        let kb = kb.apply_animation().unwrap();
+        let _ = kb.read_animation();
+
+        let reports = read_reports(&out.0.lock().unwrap()).unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0], mock.reports()[0]);
+        assert_eq!(reports[1][..6], [0x0A, 0x01, 0x01, 0x02, 0x29, 0x12]);
+
+        assert!(read_reports(b"not a capture").is_err());
+
+        // A section header, followed by a block whose length runs past the end of the file.
+        let mut truncated = vec![];
+        for word in [
+            0x0A0D_0D0A,
+            28,
+            0x1A2B_3C4D,
+            1,
+            u32::MAX,
+            u32::MAX,
+            28,
+            0xFF,
+            24,
+        ] {
+            truncated.extend(u32::to_le_bytes(word));
+        }
+        truncated.resize(48, 0);
+        assert!(matches!(
+            read_reports(&truncated),
+            Err(CaptureError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn test_capture() {
+        let out = Shared::default();
+        let capture = Capture::new(out.clone()).unwrap();
+
+        let mock = MockTransport::new();
+        let kb = Rk68::with_transport(mock.clone()).with_capture(Some(capture.clone()));
+
+        let kb = kb.apply_color().unwrap().apply_animation().unwrap();
+        assert!(kb.read_animation().is_err());
+        capture.finish().unwrap();
+
+        let blocks = blocks(&out.0.lock().unwrap());
+        assert_eq!(blocks[0].0, 0x0A0D_0D0A);
+        assert_eq!(blocks[1].0, 1);
+        assert_eq!(blocks[1].1[..2], 220u16.to_le_bytes());
+
+        // A submission, and completion for each of the 8 reports sent, and the one read.
+        let packets: Vec<_> = blocks[2..].iter().map(|(_, body)| &body[20..]).collect();
+        assert!(blocks[2..].iter().all(|(block_type, _)| *block_type == 6));
+        assert_eq!(packets.len(), 18);
+
+        // The first report is sent as a SET_REPORT for feature report 0x0A on interface 1.
+        let first = packets[0];
+        assert_eq!(first[8], b'S');
+        assert_eq!(
+            first[40..48],
+            [0x21, 0x09, 0x0A, 0x03, 0x01, 0x00, 65, 0x00]
+        );
+        // Packets are padded to 32 bits.
+        assert_eq!(first[64..64 + 65], mock.reports()[0]);
+
+        // The failed read is recorded as a GET_REPORT that did not complete.
+        let read = packets[17];
+        assert_eq!(read[8], b'C');
+        assert_eq!(read[10], 0x80);
+        assert_eq!(i32::from_le_bytes(read[28..32].try_into().unwrap()), -32);
+    }
+}
@@ -0,0 +1,81 @@
+use std::str::FromStr;
+
+use palette::{named, Srgb};
+
+/// Parse a named color such as `"red"`, or a hex color such as `"#ff0012"`.
+pub fn parse_color(s: &str) -> Option<Srgb<u8>> {
+    named::from_str(s).or_else(|| Srgb::<u8>::from_str(s).ok())
+}
+
+/// Format a color as hex, such as `"#ff0012"`.
+#[inline]
+pub fn to_hex(color: Srgb<u8>) -> String {
+    format!("#{color:x}")
+}
+
+/// (De)serialize colors as hex strings instead of palette's default of a struct per channel.
+///
+/// Named colors are also accepted when deserializing. Use as
+/// `#[serde(with = "kludged::keyboards::color::hex")]`, or `hex::map` for maps with color values.
+#[cfg(feature = "serde")]
+pub mod hex {
+    use std::{borrow::Cow, collections::BTreeMap};
+
+    use palette::Srgb;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{parse_color, to_hex};
+
+    struct Hex(Srgb<u8>);
+
+    impl Serialize for Hex {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&to_hex(self.0))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Hex {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let s = Cow::<str>::deserialize(deserializer)?;
+
+            parse_color(&s).map(Hex).ok_or_else(|| {
+                D::Error::custom(format!(
+                    r##"invalid color "{s}", expected a hex color such as "#ff0012", or a named color such as "red""##
+                ))
+            })
+        }
+    }
+
+    pub fn serialize<S: Serializer>(color: &Srgb<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+        Hex(*color).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Srgb<u8>, D::Error> {
+        Hex::deserialize(deserializer).map(|hex| hex.0)
+    }
+
+    pub mod map {
+        use super::*;
+
+        pub fn serialize<K, S>(
+            map: &BTreeMap<K, Srgb<u8>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error>
+        where
+            K: Serialize,
+            S: Serializer,
+        {
+            serializer.collect_map(map.iter().map(|(key, color)| (key, Hex(*color))))
+        }
+
+        pub fn deserialize<'de, K, D>(deserializer: D) -> Result<BTreeMap<K, Srgb<u8>>, D::Error>
+        where
+            K: Deserialize<'de> + Ord,
+            D: Deserializer<'de>,
+        {
+            let map = BTreeMap::<K, Hex>::deserialize(deserializer)?;
+
+            Ok(map.into_iter().map(|(key, hex)| (key, hex.0)).collect())
+        }
+    }
+}
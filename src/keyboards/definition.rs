@@ -0,0 +1,544 @@
+//! Keyboard models described by data, instead of code.
+//!
+//! A [`ModelDefinition`] holds everything needed to find a keyboard, and build the reports of its
+//! settings. Definitions are written in TOML, and are either compiled in with
+//! [`ModelDefinition::from_toml`], or loaded from `$XDG_CONFIG_HOME/kludged/models` with
+//! [`ModelDefinition::load_all`]. A keyboard is then driven by a
+//! [`GenericKeyboard`](super::generic::GenericKeyboard).
+//!
+//! See `models/rk68.toml` in the repository for a definition of the RK68. The `[[keys]]` tables
+//! are in the same format `kludged probe` writes.
+
+#[cfg(feature = "profiles")]
+use std::{fs, io::ErrorKind, path::Path};
+
+use hidapi::DeviceInfo;
+use strum::VariantNames;
+
+use crate::errors::DefinitionError;
+
+use super::{layout::KeyIndexes, on_interface, steps::Indexes, Model};
+
+/// File extension of model definitions.
+#[cfg(feature = "profiles")]
+const EXTENSION: &str = "toml";
+
+/// Names a model can not have, as they are already used by the commands of kludged, or the built
+/// in models.
+pub const RESERVED_MODEL_NAMES: &[&str] = &[
+    "udev",
+    "list",
+    "decode",
+    "probe",
+    "daemon",
+    "help",
+    "set-color",
+    "set-anim",
+    "set-keys",
+    "set-gradient",
+    "get",
+    "profile",
+    "check-partial-updates",
+];
+
+/// Names an option can not have, as they are already used by the arguments of kludged.
+pub const RESERVED_OPTION_NAMES: &[&str] = &[
+    "color",
+    "key",
+    "default",
+    "anim",
+    "gui",
+    "verbose",
+    "quiet",
+    "help",
+    "packet-delay",
+    "packet-retries",
+    "retry-backoff",
+    "transaction-retries",
+    "device",
+    "all",
+    "model",
+    "capture",
+    "dry-run",
+];
+
+/// A keyboard model, and the layout of the reports its settings are sent with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub struct ModelDefinition {
+    /// Name of the model in kebab-case, used for its subcommand.
+    pub name: String,
+
+    /// Every vendor, and product ID the model is sold with.
+    pub ids: Vec<UsbId>,
+
+    /// Interface the settings are sent to.
+    pub interface: i32,
+    pub usage_page: u16,
+    pub usage: u16,
+
+    pub color: ReportDefinition,
+    pub animation: Option<AnimationDefinition>,
+
+    /// Where the color of each key is stored in the color reports.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub keys: Vec<KeyIndexes>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UsbId {
+    pub vid: u16,
+    pub pid: u16,
+}
+
+/// The reports a setting is sent with.
+///
+/// Indexes are counted from the start of each report, which starts with the report ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub struct ReportDefinition {
+    /// Number of reports the setting is split into.
+    #[cfg_attr(feature = "serde", serde(default = "one"))]
+    pub step_count: usize,
+
+    /// Length of each report.
+    pub step_len: usize,
+
+    /// Bytes every report starts with, including the report ID.
+    pub header: Vec<u8>,
+
+    /// Index of the byte holding the number of the report, counting from one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub step_counter: Option<usize>,
+
+    /// Bytes that are always sent with the same value.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bytes: Vec<FixedBytes>,
+
+    /// Settings stored in a single byte.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub options: Vec<OptionDefinition>,
+}
+
+#[cfg(feature = "serde")]
+fn one() -> usize {
+    1
+}
+
+/// Bytes that are always sent with the same value, starting at `index`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FixedBytes {
+    pub index: Indexes,
+    pub values: Vec<u8>,
+}
+
+/// A setting stored in a single byte, such as the sleep duration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptionDefinition {
+    /// Name of the option in kebab-case, used for its argument.
+    pub name: String,
+
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub values: ValuesDefinition,
+}
+
+/// The possible values of a byte, which are numbered from `first` onwards.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ValuesDefinition {
+    pub index: Indexes,
+
+    /// The value of the first name.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub first: u8,
+    pub values: Vec<String>,
+
+    /// Value used until another one is set. Defaults to the first one.
+    pub default: Option<String>,
+}
+
+impl ValuesDefinition {
+    /// The byte a value is stored as.
+    pub fn value(&self, name: &str) -> Option<u8> {
+        let position = self.values.iter().position(|value| value == name)?;
+
+        u8::try_from(position).ok()?.checked_add(self.first)
+    }
+
+    /// The byte of the default value.
+    pub fn default_value(&self) -> u8 {
+        self.default
+            .as_deref()
+            .and_then(|name| self.value(name))
+            .unwrap_or(self.first)
+    }
+}
+
+/// The report an animation is sent with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub struct AnimationDefinition {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub report: ReportDefinition,
+
+    /// The byte the animation is stored in, and the names of the animations.
+    pub animation: ValuesDefinition,
+
+    /// Index of the red value of the animation color, followed by green, and blue.
+    pub color: Option<Indexes>,
+}
+
+impl ModelDefinition {
+    /// Parse, and validate a definition.
+    #[cfg(feature = "profiles")]
+    pub fn from_toml(document: &str) -> Result<Self, DefinitionError> {
+        let definition: Self = toml::from_str(document)?;
+        definition.validate()?;
+
+        Ok(definition)
+    }
+
+    /// Load every definition in `$XDG_CONFIG_HOME/kludged/models`, sorted by file name.
+    ///
+    /// A missing directory is treated the same as an empty one. See [`ModelDefinition::load_dir`]
+    /// for how files that can not be loaded are returned.
+    #[cfg(feature = "profiles")]
+    pub fn load_all() -> Result<Vec<Result<Self, DefinitionError>>, DefinitionError> {
+        let dir = crate::profiles::config_dir()
+            .ok_or(DefinitionError::NoConfigDir)?
+            .join("models");
+
+        Self::load_dir(&dir)
+    }
+
+    /// Load every definition in a directory, sorted by file name.
+    ///
+    /// Each file is loaded on its own, so that a broken file does not keep the others from being
+    /// used. A file that can not be loaded is returned as a [`DefinitionError::File`] in its
+    /// place. Only errors reading the directory itself are returned as a whole.
+    #[cfg(feature = "profiles")]
+    pub fn load_dir(dir: &Path) -> Result<Vec<Result<Self, DefinitionError>>, DefinitionError> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut paths = vec![];
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.extension().is_some_and(|ext| ext == EXTENSION) {
+                paths.push(path);
+            }
+        }
+
+        paths.sort();
+
+        let definitions = paths
+            .into_iter()
+            .map(|path| {
+                fs::read_to_string(&path)
+                    .map_err(DefinitionError::from)
+                    .and_then(|document| Self::from_toml(&document))
+                    .map_err(|err| DefinitionError::File {
+                        path,
+                        source: Box::new(err),
+                    })
+            })
+            .collect();
+
+        Ok(definitions)
+    }
+
+    /// Check that every index is inside of its reports, that no two keys share a byte, and that
+    /// no name is already used by kludged.
+    pub fn validate(&self) -> Result<(), DefinitionError> {
+        let invalid = |reason: String| Err(DefinitionError::Invalid(reason));
+
+        if !is_kebab_case(&self.name) {
+            return invalid(format!("the name \"{}\" is not in kebab-case", self.name));
+        }
+
+        if RESERVED_MODEL_NAMES.contains(&self.name.as_str())
+            || Model::VARIANTS.contains(&self.name.as_str())
+        {
+            return invalid(format!("the name \"{}\" is used by kludged", self.name));
+        }
+
+        if self.ids.is_empty() {
+            return invalid("no vendor, and product IDs were provided".to_owned());
+        }
+
+        self.color.validate("color")?;
+
+        let mut used = vec![false; self.color.data_len()];
+        for key in &self.keys {
+            if !is_kebab_case(&key.key) {
+                return invalid(format!("the key name \"{}\" is not in kebab-case", key.key));
+            }
+
+            if self
+                .keys
+                .iter()
+                .filter(|other| other.key == key.key)
+                .count()
+                > 1
+            {
+                return invalid(format!("the key \"{}\" is defined more than once", key.key));
+            }
+
+            for index in &key.indexes {
+                let Some(offset) = self.color.offset(index) else {
+                    return invalid(format!("the key \"{}\" is outside of the reports", key.key));
+                };
+
+                if index.index < self.color.header.len() || used[offset] {
+                    return invalid(format!(
+                        "the key \"{}\" overlaps the header, or another key",
+                        key.key
+                    ));
+                }
+
+                used[offset] = true;
+            }
+        }
+
+        if let Some(animation) = &self.animation {
+            animation.report.validate("animation")?;
+            animation
+                .report
+                .validate_values("animation", &animation.animation)?;
+
+            if let Some(color) = &animation.color {
+                let last = Indexes::new(color.step, color.index + 2);
+                if animation.report.offset(&last).is_none() {
+                    return invalid("the animation color is outside of the report".to_owned());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether a device is the one the settings of this model are sent to.
+    pub fn matches(&self, device_info: &DeviceInfo) -> bool {
+        self.ids
+            .iter()
+            .any(|id| id.vid == device_info.vendor_id() && id.pid == device_info.product_id())
+            && on_interface(device_info, self.interface)
+            && device_info.usage_page() == self.usage_page
+            && device_info.usage() == self.usage
+    }
+}
+
+impl ReportDefinition {
+    /// Total length of the reports.
+    #[inline(always)]
+    pub fn data_len(&self) -> usize {
+        self.step_count * self.step_len
+    }
+
+    /// Offset of an index from the start of the first report, if it is inside of the reports.
+    pub fn offset(&self, index: &Indexes) -> Option<usize> {
+        (index.step < self.step_count && index.index < self.step_len)
+            .then_some(index.step * self.step_len + index.index)
+    }
+
+    /// The reports with nothing but the header, step counter, fixed bytes, and default options
+    /// set.
+    pub fn initial_data(&self) -> Vec<u8> {
+        let mut data = vec![0; self.data_len()];
+
+        for (i, step) in data.chunks_exact_mut(self.step_len).enumerate() {
+            step[..self.header.len()].copy_from_slice(&self.header);
+
+            if let Some(counter) = self.step_counter {
+                step[counter] = i as u8 + 1;
+            }
+        }
+
+        for bytes in &self.bytes {
+            for (i, value) in bytes.values.iter().enumerate() {
+                data[bytes.index.step * self.step_len + bytes.index.index + i] = *value;
+            }
+        }
+
+        for option in &self.options {
+            let offset = option.values.index.step * self.step_len + option.values.index.index;
+            data[offset] = option.values.default_value();
+        }
+
+        data
+    }
+
+    fn validate(&self, setting: &str) -> Result<(), DefinitionError> {
+        let invalid = |reason: &str| Err(DefinitionError::Invalid(format!("{setting} {reason}")));
+
+        if self.step_count == 0 || self.header.is_empty() || self.header.len() > self.step_len {
+            return invalid("reports need at least one step, and a header that fits in them");
+        }
+
+        if self
+            .step_counter
+            .is_some_and(|counter| counter >= self.step_len)
+        {
+            return invalid("step counter is outside of the reports");
+        }
+
+        for bytes in &self.bytes {
+            let last = Indexes::new(
+                bytes.index.step,
+                bytes.index.index + bytes.values.len().saturating_sub(1),
+            );
+
+            if self.offset(&bytes.index).is_none() || self.offset(&last).is_none() {
+                return invalid("fixed bytes are outside of the reports");
+            }
+        }
+
+        for option in &self.options {
+            if !is_kebab_case(&option.name) {
+                return invalid(&format!("option \"{}\" is not in kebab-case", option.name));
+            }
+
+            if RESERVED_OPTION_NAMES.contains(&option.name.as_str()) {
+                return invalid(&format!("option \"{}\" is used by kludged", option.name));
+            }
+
+            self.validate_values(setting, &option.values)?;
+        }
+
+        Ok(())
+    }
+
+    fn validate_values(
+        &self,
+        setting: &str,
+        values: &ValuesDefinition,
+    ) -> Result<(), DefinitionError> {
+        let invalid = |reason: &str| Err(DefinitionError::Invalid(format!("{setting} {reason}")));
+
+        if self.offset(&values.index).is_none() {
+            return invalid("values are stored outside of the reports");
+        }
+
+        if values.values.is_empty() || usize::from(values.first) + values.values.len() > 256 {
+            return invalid("values need at least one name, and have to fit in a byte");
+        }
+
+        if let Some(default) = &values.default {
+            if !values.values.contains(default) {
+                return invalid(&format!("default \"{default}\" is not one of the values"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn is_kebab_case(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+#[cfg(all(test, feature = "profiles"))]
+mod tests {
+    use crate::errors::DefinitionError;
+
+    use super::ModelDefinition;
+
+    const RK68: &str = include_str!("../../models/rk68.toml");
+
+    #[test]
+    fn test_rk68_definition() {
+        let definition = ModelDefinition::from_toml(RK68).unwrap();
+        assert_eq!(definition.keys.len(), 68);
+
+        let animation = definition.animation.unwrap();
+        assert_eq!(animation.animation.value("neon-stream"), Some(1));
+        assert_eq!(animation.animation.value("flash-away"), Some(20));
+        assert_eq!(animation.animation.value("unknown"), None);
+    }
+
+    #[test]
+    fn test_invalid_definition() {
+        let overlapping = RK68.replacen("[0, 10]", "[0, 9]", 1);
+        assert!(matches!(
+            ModelDefinition::from_toml(&overlapping),
+            Err(DefinitionError::Invalid(_))
+        ));
+
+        let outside = RK68.replacen("[0, 10]", "[7, 10]", 1);
+        assert!(matches!(
+            ModelDefinition::from_toml(&outside),
+            Err(DefinitionError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_dir() {
+        use std::{env, fs, process};
+
+        let dir = env::temp_dir().join(format!("kludged-models-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("broken.toml"), "name = ").unwrap();
+        fs::write(dir.join("rk68.toml"), RK68).unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        // The broken file does not keep the other one from loading.
+        let definitions = ModelDefinition::load_dir(&dir).unwrap();
+        assert_eq!(definitions.len(), 2);
+        assert!(matches!(definitions[0], Err(DefinitionError::File { .. })));
+        assert_eq!(definitions[1].as_ref().unwrap().keys.len(), 68);
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(ModelDefinition::load_dir(&dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reserved_names() {
+        let name = |name: &str| {
+            RK68.replacen(
+                "name = \"rk68-definition\"",
+                &format!("name = \"{name}\""),
+                1,
+            )
+        };
+        assert!(ModelDefinition::from_toml(&name("rk61")).is_ok());
+
+        for reserved in ["rk68", "list", "udev", "help", "set-color"] {
+            assert!(matches!(
+                ModelDefinition::from_toml(&name(reserved)),
+                Err(DefinitionError::Invalid(_))
+            ));
+        }
+
+        for reserved in [
+            "color", "key", "default", "anim", "device", "all", "capture",
+        ] {
+            let option = RK68.replacen("name = \"sleep\"", &format!("name = \"{reserved}\""), 1);
+            assert!(matches!(
+                ModelDefinition::from_toml(&option),
+                Err(DefinitionError::Invalid(_))
+            ));
+        }
+    }
+}
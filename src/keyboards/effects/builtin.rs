@@ -0,0 +1,184 @@
+//! A few effects to get started with.
+
+use std::{f32::consts::TAU, time::Duration};
+
+use palette::{FromColor, Hsv, Srgb};
+
+use crate::keyboards::{
+    gradient::{project, Interpolation},
+    layout::Layout,
+};
+
+use super::{Effect, Frame};
+
+/// Bands of color moving across the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wave {
+    pub color: Srgb<u8>,
+    pub background: Srgb<u8>,
+
+    /// Direction the wave moves in, in degrees. Uses the same convention as
+    /// [`Shape::Linear`](crate::keyboards::gradient::Shape::Linear).
+    pub angle: f32,
+
+    /// Distance between two bands, in key units.
+    pub wavelength: f32,
+
+    /// Key units moved per second.
+    pub speed: f32,
+}
+
+impl Default for Wave {
+    fn default() -> Self {
+        Self {
+            color: Srgb::new(0, 128, 255),
+            background: Srgb::new(0, 0, 0),
+            angle: 0.0,
+            wavelength: 8.0,
+            speed: 8.0,
+        }
+    }
+}
+
+impl<K: Copy + PartialEq> Effect<K> for Wave {
+    fn render(&mut self, t: Duration, layout: &Layout<K>, frame: &mut Frame<K>) {
+        let offset = self.speed * t.as_secs_f32();
+
+        for ((_, geometry), (_, color)) in layout.keys().zip(frame.iter_mut()) {
+            let phase = (project(geometry.center(), self.angle) - offset) / self.wavelength;
+            let intensity = 0.5 + 0.5 * (phase * TAU).cos();
+
+            *color = Interpolation::Oklab.mix(self.background, self.color, intensity);
+        }
+    }
+}
+
+/// Every key fading in, and out on its own.
+///
+/// The keys are spread evenly over the period, so that the keyboard as a whole never goes dark.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Breathing {
+    pub color: Srgb<u8>,
+
+    /// Time for a key to fade in, and back out.
+    pub period: Duration,
+}
+
+impl Default for Breathing {
+    fn default() -> Self {
+        Self {
+            color: Srgb::new(255, 255, 255),
+            period: Duration::from_secs(4),
+        }
+    }
+}
+
+impl<K: Copy + PartialEq> Effect<K> for Breathing {
+    fn render(&mut self, t: Duration, _: &Layout<K>, frame: &mut Frame<K>) {
+        let progress = t.as_secs_f32() / self.period.as_secs_f32().max(f32::EPSILON);
+
+        for (i, (_, color)) in frame.iter_mut().enumerate() {
+            // Multiples of the golden ratio are spread evenly, and neighbouring keys end up far
+            // apart, which looks less like a wave than spreading them in order.
+            let offset = (i as f32 * 0.618_034).fract();
+            let intensity = 0.5 - 0.5 * ((progress + offset) * TAU).cos();
+
+            let (red, green, blue) = self.color.into_format::<f32>().into_components();
+            *color = Srgb::new(red * intensity, green * intensity, blue * intensity).into_format();
+        }
+    }
+}
+
+/// A rainbow sweeping across the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rainbow {
+    /// Direction the rainbow moves in, in degrees. Uses the same convention as
+    /// [`Shape::Linear`](crate::keyboards::gradient::Shape::Linear).
+    pub angle: f32,
+
+    /// Number of times the full rainbow fits on the keyboard.
+    pub repeat: f32,
+
+    /// Degrees of hue the rainbow moves per second.
+    pub speed: f32,
+}
+
+impl Default for Rainbow {
+    fn default() -> Self {
+        Self {
+            angle: 0.0,
+            repeat: 1.0,
+            speed: 90.0,
+        }
+    }
+}
+
+impl<K: Copy + PartialEq> Effect<K> for Rainbow {
+    fn render(&mut self, t: Duration, layout: &Layout<K>, frame: &mut Frame<K>) {
+        let shift = self.speed * t.as_secs_f32();
+        let length = layout.width().max(f32::EPSILON);
+
+        for ((_, geometry), (_, color)) in layout.keys().zip(frame.iter_mut()) {
+            let position = project(geometry.center(), self.angle) / length;
+            let hue = position * self.repeat * 360.0 - shift;
+
+            *color = Srgb::from_color(Hsv::new(hue, 1.0, 1.0)).into_format();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use palette::Srgb;
+
+    use crate::keyboards::{
+        effects::{Effect, Frame},
+        rk68::keys::Keys,
+    };
+
+    use super::{Breathing, Rainbow, Wave};
+
+    #[test]
+    fn test_effects_change_over_time() {
+        let layout = Keys::layout();
+        let effects: [&mut dyn Effect<Keys>; 3] = [
+            &mut Wave::default(),
+            &mut Breathing::default(),
+            &mut Rainbow::default(),
+        ];
+
+        for effect in effects {
+            let mut first = Frame::new(&layout);
+            effect.render(Duration::ZERO, &layout, &mut first);
+
+            let mut second = first.clone();
+            effect.render(Duration::from_millis(500), &layout, &mut second);
+
+            assert_ne!(first, second);
+
+            // Different keys are lit differently at the same time.
+            assert!(first
+                .iter()
+                .any(|(_, color)| color != first.iter().next().unwrap().1));
+        }
+    }
+
+    #[test]
+    fn test_wave() {
+        let layout = Keys::layout();
+        let mut frame = Frame::new(&layout);
+
+        let mut wave = Wave {
+            color: Srgb::new(255, 0, 0),
+            wavelength: 100.0,
+            speed: 0.0,
+            ..Default::default()
+        };
+        wave.render(Duration::ZERO, &layout, &mut frame);
+
+        // Near the crest of a long wave, keys are close to the wave color.
+        assert!(frame.get(Keys::Esc).unwrap().red > 200);
+    }
+}
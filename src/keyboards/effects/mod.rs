@@ -0,0 +1,122 @@
+//! Effects rendered on the host, and streamed to the keyboard frame by frame.
+//!
+//! Unlike the animations built into a keyboard's firmware, an [`Effect`] can color each key
+//! however it likes. A [`scheduler::Scheduler`] renders the effect at a target frame rate, and
+//! writes each [`Frame`] to the keyboard.
+
+pub mod builtin;
+pub mod scheduler;
+
+use std::time::Duration;
+
+use palette::Srgb;
+
+use super::{layout::Layout, KeyboardPerKeyColorable};
+
+/// An effect that produces a [`Frame`] for any point in time.
+///
+/// Effects are free to keep state between calls, but should not assume that every frame is
+/// rendered. If the keyboard can not keep up, frames are dropped, and `t` skips ahead.
+pub trait Effect<K> {
+    /// Render the frame at `t`, where `t` is the time since the effect started.
+    ///
+    /// The frame still holds the colors of the previous frame.
+    fn render(&mut self, t: Duration, layout: &Layout<K>, frame: &mut Frame<K>);
+}
+
+impl<K, F> Effect<K> for F
+where
+    F: FnMut(Duration, &Layout<K>, &mut Frame<K>),
+{
+    #[inline]
+    fn render(&mut self, t: Duration, layout: &Layout<K>, frame: &mut Frame<K>) {
+        self(t, layout, frame)
+    }
+}
+
+/// The color of every key for a single frame of an [`Effect`].
+///
+/// Keys are stored in the same order as the [`Layout`] the frame was created from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame<K> {
+    colors: Vec<(K, Srgb<u8>)>,
+}
+
+impl<K: Copy + PartialEq> Frame<K> {
+    /// A frame where every key in the layout is turned off.
+    pub fn new(layout: &Layout<K>) -> Self {
+        Self {
+            colors: layout
+                .keys()
+                .map(|(key, _)| (key, Srgb::new(0, 0, 0)))
+                .collect(),
+        }
+    }
+
+    /// Set the color of a key. Keys that are not part of the frame are ignored.
+    pub fn set<C: Into<Srgb<u8>>>(&mut self, key: K, color: C) {
+        if let Some((_, c)) = self.colors.iter_mut().find(|(k, _)| *k == key) {
+            *c = color.into();
+        }
+    }
+
+    /// The color of a key, if it is part of the frame.
+    pub fn get(&self, key: K) -> Option<Srgb<u8>> {
+        self.colors
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, color)| *color)
+    }
+
+    /// Set every key to the same color.
+    pub fn fill<C: Into<Srgb<u8>>>(&mut self, color: C) {
+        let color = color.into();
+
+        self.colors.iter_mut().for_each(|(_, c)| *c = color);
+    }
+
+    /// Iterator over every key, and its color.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (K, Srgb<u8>)> + '_ {
+        self.colors.iter().copied()
+    }
+
+    /// Iterator over every key, and a mutable reference to its color.
+    ///
+    /// Keys are in the same order as [`Layout::keys`], so the two can be zipped together.
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (K, &mut Srgb<u8>)> {
+        self.colors.iter_mut().map(|(key, color)| (*key, color))
+    }
+
+    /// Store the colors of the frame in a keyboard.
+    ///
+    /// Like [`KeyboardPerKeyColorable::set_key_color`], the colors are only written once
+    /// [`KeyboardColorable::apply_color`](super::KeyboardColorable::apply_color) is called.
+    pub fn paint<KB: KeyboardPerKeyColorable<Key = K>>(&self, kb: KB) -> KB {
+        self.iter()
+            .fold(kb, |kb, (key, color)| kb.set_key_color(key, color))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use palette::Srgb;
+
+    use crate::keyboards::rk68::keys::Keys;
+
+    use super::Frame;
+
+    #[test]
+    fn test_frame() {
+        let mut frame = Frame::new(&Keys::layout());
+        assert_eq!(frame.iter().count(), 68);
+        assert_eq!(frame.get(Keys::Esc), Some(Srgb::new(0, 0, 0)));
+
+        frame.set(Keys::Esc, Srgb::new(255, 0, 0));
+        assert_eq!(frame.get(Keys::Esc), Some(Srgb::new(255, 0, 0)));
+
+        frame.fill(Srgb::new(0, 0, 255));
+        assert!(frame.iter().all(|(_, color)| color == Srgb::new(0, 0, 255)));
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use hidapi::HidResult;
+
+use crate::keyboards::{KeyboardLayout, KeyboardPerKeyColorable};
+
+use super::{Effect, Frame};
+
+/// Stops a running [`Scheduler`] from another thread.
+///
+/// The frame being written when the handle is used is finished first, so the keyboard is never
+/// left with half of a frame.
+#[derive(Clone, Debug, Default)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    #[inline]
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// What happened during a [`Scheduler::run`].
+#[derive(Debug)]
+pub struct Finished<KB> {
+    /// The keyboard, holding the colors of the last frame.
+    pub keyboard: KB,
+
+    /// Number of frames written to the keyboard.
+    pub rendered: u64,
+
+    /// Number of frames skipped, because the keyboard could not keep up with the frame rate.
+    pub dropped: u64,
+}
+
+/// Renders an [`Effect`] at a target frame rate, and writes each frame to a keyboard.
+///
+/// If rendering, and writing a frame takes longer than the frame interval, the frames that
+/// should have been written in the meantime are dropped instead of being written late.
+///
+/// ### Note:
+/// Each frame is written with [`KeyboardColorable::apply_color`](crate::keyboards::KeyboardColorable::apply_color).
+/// For an RK68 that takes at least 35ms, which caps the frame rate at about 28 frames per
+/// second. Run the scheduler on an [`Rk68Session`](crate::keyboards::rk68::session::Rk68Session)
+/// so that the device is not reopened for every frame.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use kludged::keyboards::{effects::{builtin::Rainbow, scheduler::Scheduler}, rk68::{session::Rk68Session, Rk68}, Keyboard};
+/// let scheduler = Scheduler::new(25).max_duration(Duration::from_secs(10));
+///
+/// let session = Rk68Session::open(Rk68::new()?)?;
+/// let finished = scheduler.run(session, &mut Rainbow::default())?;
+/// println!("dropped {} frames", finished.dropped);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Scheduler {
+    interval: Duration,
+    max_frames: Option<u64>,
+    max_duration: Option<Duration>,
+    stop: StopHandle,
+}
+
+impl Scheduler {
+    /// Create a scheduler running at `fps` frames per second.
+    ///
+    /// Frames are at least a nanosecond apart, higher rates are capped to that.
+    ///
+    /// # Panics:
+    /// Will cause a panic if `fps` is zero.
+    pub fn new(fps: u32) -> Self {
+        assert!(fps > 0, "the frame rate must be at least one");
+
+        Self {
+            interval: (Duration::from_secs(1) / fps).max(Duration::from_nanos(1)),
+            max_frames: None,
+            max_duration: None,
+            stop: StopHandle::default(),
+        }
+    }
+
+    /// Stop after rendering `frames` frames.
+    #[inline]
+    pub fn max_frames(mut self, frames: u64) -> Self {
+        self.max_frames = Some(frames);
+        self
+    }
+
+    /// Stop once `duration` has passed.
+    #[inline]
+    pub fn max_duration(mut self, duration: Duration) -> Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    /// A handle that stops the scheduler once used.
+    #[inline]
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Time between each frame.
+    #[inline(always)]
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Render the effect until stopped, or a limit is reached.
+    ///
+    /// Returns early if writing a frame fails.
+    pub fn run<KB, E>(&self, mut kb: KB, effect: &mut E) -> HidResult<Finished<KB>>
+    where
+        KB: KeyboardPerKeyColorable + KeyboardLayout<Key = <KB as KeyboardPerKeyColorable>::Key>,
+        <KB as KeyboardPerKeyColorable>::Key: Copy + PartialEq,
+        E: Effect<<KB as KeyboardPerKeyColorable>::Key>,
+    {
+        let layout = KB::layout();
+        let mut frame = Frame::new(&layout);
+
+        let (mut rendered, mut dropped) = (0, 0);
+
+        let start = Instant::now();
+        let mut next = start;
+
+        loop {
+            let t = next - start;
+
+            let is_done = self.stop.is_stopped()
+                || self.max_frames.is_some_and(|max| rendered >= max)
+                || self.max_duration.is_some_and(|max| t >= max);
+            if is_done {
+                break;
+            }
+
+            effect.render(t, &layout, &mut frame);
+            kb = frame.paint(kb).apply_color()?;
+            rendered += 1;
+
+            next += self.interval;
+
+            let now = Instant::now();
+            if now < next {
+                sleep(next - now);
+            } else {
+                // Skip the frames we are too late for. The frame after that is rendered right
+                // away, as it is less than an interval late.
+                let missed = ((now - next).as_nanos() / self.interval.as_nanos()) as u32;
+
+                dropped += u64::from(missed);
+                next += self.interval * missed;
+            }
+        }
+
+        Ok(Finished {
+            keyboard: kb,
+            rendered,
+            dropped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use palette::Srgb;
+
+    use crate::keyboards::{
+        effects::Frame,
+        layout::Layout,
+        mock::MockTransport,
+        rk68::{keys::Keys, mock::ColorFrame, Rk68},
+    };
+
+    use super::Scheduler;
+
+    #[test]
+    fn test_scheduler() {
+        let transport = MockTransport::new();
+        let kb = Rk68::with_transport(transport.clone());
+
+        let mut times = vec![];
+        let mut effect = |t: Duration, _: &Layout<Keys>, frame: &mut Frame<Keys>| {
+            times.push(t);
+            frame.set(Keys::Esc, Srgb::new(times.len() as u8, 0, 0));
+        };
+
+        let finished = Scheduler::new(1000)
+            .max_frames(3)
+            .run(kb, &mut effect)
+            .unwrap();
+
+        assert_eq!(finished.rendered, 3);
+        assert_eq!(times[0], Duration::ZERO);
+        assert!(times.windows(2).all(|t| t[0] < t[1]));
+
+        // Each frame takes far longer than a millisecond to write, so frames must be dropped.
+        assert!(finished.dropped > 0);
+
+        let reports = transport.reports();
+        assert_eq!(reports.len(), 21);
+
+        let last = ColorFrame::from_reports(&reports[14..]).unwrap();
+        assert_eq!(last.key_color(Keys::Esc), Srgb::new(3, 0, 0));
+    }
+
+    #[test]
+    fn test_high_frame_rate() {
+        let scheduler = Scheduler::new(u32::MAX).max_frames(2);
+        assert_eq!(scheduler.interval(), Duration::from_nanos(1));
+
+        let kb = Rk68::with_transport(MockTransport::new());
+        let finished = scheduler
+            .run(kb, &mut |_: Duration,
+                           _: &Layout<Keys>,
+                           _: &mut Frame<Keys>| {})
+            .unwrap();
+
+        assert_eq!(finished.rendered, 2);
+    }
+
+    #[test]
+    fn test_stop_handle() {
+        let scheduler = Scheduler::new(30);
+        scheduler.stop_handle().stop();
+
+        let kb = Rk68::with_transport(MockTransport::new());
+        let finished = scheduler
+            .run(kb, &mut |_: Duration,
+                           _: &Layout<Keys>,
+                           _: &mut Frame<Keys>| {})
+            .unwrap();
+
+        assert_eq!(finished.rendered, 0);
+    }
+}
@@ -0,0 +1,418 @@
+use std::sync::Arc;
+
+use hidapi::{DeviceInfo, HidApi, HidResult};
+use palette::Srgb;
+
+use crate::errors::{DefinitionError, DeviceNotFound, GetDeviceError};
+
+use super::{
+    capture::{Capture, CaptureTransport},
+    definition::{ModelDefinition, ReportDefinition},
+    policy::WritePolicy,
+    selector::{DeviceSelector, Target},
+    steps::Indexes,
+    transport::{HidTransport, Transport, TransportDevice},
+};
+
+/// A keyboard driven by a [`ModelDefinition`].
+///
+/// Works the same way as a built in model, but keys, animations, and options are referred to by
+/// the names given in the definition.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use kludged::keyboards::{definition::ModelDefinition, generic::GenericKeyboard, transport::DryRunTransport};
+/// # use palette::named;
+/// let definition = ModelDefinition::from_toml(include_str!("../../models/rk68.toml")).unwrap();
+/// let transport = DryRunTransport::new();
+///
+/// GenericKeyboard::with_transport(Arc::new(definition), transport.clone())
+///     .set_key_color("esc", named::RED)?
+///     .apply_color()?;
+///
+/// assert_eq!(transport.reports().len(), 7);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct GenericKeyboard<T: Transport = HidTransport> {
+    definition: Arc<ModelDefinition>,
+    transport: T,
+    write_policy: WritePolicy,
+    color: Vec<u8>,
+    animation: Vec<u8>,
+}
+
+impl GenericKeyboard {
+    /// Create a keyboard via [`DeviceInfo`], without checking if the device matches the
+    /// definition.
+    ///
+    /// # ⚠️ Warning:
+    /// The device being written to can be bricked, or bugged when a write is performed, if the
+    /// definition does not describe it.
+    pub fn new_unchecked(definition: Arc<ModelDefinition>, device_info: DeviceInfo) -> Self {
+        Self::with_transport(definition, HidTransport::new(device_info))
+    }
+
+    /// The same as [`Keyboard::get_from_devices`](super::Keyboard::get_from_devices), for a model
+    /// that is only known at runtime.
+    pub fn get_from_devices(
+        definition: &Arc<ModelDefinition>,
+        devices: &mut Vec<&DeviceInfo>,
+    ) -> Option<Self> {
+        let index = devices.iter().position(|inf| definition.matches(inf))?;
+        let device_info = devices.remove(index).clone();
+
+        Some(Self::new_unchecked(Arc::clone(definition), device_info))
+    }
+
+    /// Every plugged in keyboard of the model, in the order `kludged list` shows them.
+    pub fn all(definition: &Arc<ModelDefinition>) -> HidResult<Vec<Self>> {
+        let mut hid_api = HidApi::new()?;
+
+        hid_api.reset_devices()?;
+        for id in &definition.ids {
+            hid_api.add_devices(id.vid, id.pid)?;
+        }
+
+        let mut devices = hid_api.device_list().collect();
+
+        Ok(std::iter::from_fn(|| Self::get_from_devices(definition, &mut devices)).collect())
+    }
+
+    /// The keyboards picked out by `target`. Never empty, an error is returned instead.
+    pub fn from_target(
+        definition: &Arc<ModelDefinition>,
+        target: &Target,
+    ) -> Result<Vec<Self>, GetDeviceError> {
+        let keyboards = Self::all(definition)?;
+
+        let keyboards: Vec<_> = match target {
+            Target::First => keyboards.into_iter().take(1).collect(),
+            Target::Device(DeviceSelector::Index(index)) => {
+                keyboards.into_iter().skip(*index).take(1).collect()
+            }
+            Target::Device(selector) => keyboards
+                .into_iter()
+                .filter(|kb| selector.matches(kb.transport.device_info()))
+                .take(1)
+                .collect(),
+            Target::All => keyboards,
+        };
+
+        if keyboards.is_empty() {
+            Err(DeviceNotFound)?
+        }
+
+        Ok(keyboards)
+    }
+}
+
+impl<T: Transport> GenericKeyboard<T> {
+    /// Create a keyboard that writes through the provided [`Transport`].
+    ///
+    /// The definition is expected to be valid, see [`ModelDefinition::validate`].
+    pub fn with_transport(definition: Arc<ModelDefinition>, transport: T) -> Self {
+        let color = definition.color.initial_data();
+        let animation = definition
+            .animation
+            .as_ref()
+            .map(|animation| {
+                let mut data = animation.report.initial_data();
+                data[offset(&animation.report, &animation.animation.index)] =
+                    animation.animation.default_value();
+
+                data
+            })
+            .unwrap_or_default();
+
+        Self {
+            definition,
+            transport,
+            write_policy: WritePolicy::default(),
+            color,
+            animation,
+        }
+    }
+
+    #[inline(always)]
+    pub fn definition(&self) -> &ModelDefinition {
+        &self.definition
+    }
+
+    /// The transport used to reach the keyboard.
+    #[inline(always)]
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Set how packets are written to the keyboard.
+    #[inline]
+    pub fn with_write_policy(mut self, write_policy: WritePolicy) -> Self {
+        self.write_policy = write_policy;
+        self
+    }
+
+    /// Record every report sent to, and read from the keyboard to `capture`.
+    pub fn with_capture(self, capture: Option<Capture>) -> GenericKeyboard<CaptureTransport<T>> {
+        let interface = self.definition.interface as u16;
+
+        GenericKeyboard {
+            transport: CaptureTransport::new(self.transport, capture, interface),
+            definition: self.definition,
+            write_policy: self.write_policy,
+            color: self.color,
+            animation: self.animation,
+        }
+    }
+
+    /// Set the color of every key.
+    pub fn set_color<C: Into<Srgb<u8>>>(mut self, color: C) -> Self {
+        let color: Srgb<u8> = color.into();
+
+        for key in &self.definition.keys {
+            set_rgb(&mut self.color, &self.definition.color, &key.indexes, color);
+        }
+
+        self
+    }
+
+    pub fn set_key_color<C: Into<Srgb<u8>>>(
+        mut self,
+        key: &str,
+        color: C,
+    ) -> Result<Self, DefinitionError> {
+        let indexes = &self
+            .definition
+            .keys
+            .iter()
+            .find(|k| k.key == key)
+            .ok_or_else(|| DefinitionError::UnknownKey(key.to_owned()))?
+            .indexes;
+
+        set_rgb(
+            &mut self.color,
+            &self.definition.color,
+            indexes,
+            color.into(),
+        );
+
+        Ok(self)
+    }
+
+    /// Set one of the options of the color setting, such as `("sleep", "never")`.
+    pub fn set_color_option(mut self, option: &str, value: &str) -> Result<Self, DefinitionError> {
+        set_option(&mut self.color, &self.definition.color, option, value)?;
+
+        Ok(self)
+    }
+
+    pub fn set_animation(mut self, animation: &str) -> Result<Self, DefinitionError> {
+        let definition = self
+            .definition
+            .animation
+            .as_ref()
+            .ok_or(DefinitionError::NoAnimation)?;
+
+        let value =
+            definition
+                .animation
+                .value(animation)
+                .ok_or_else(|| DefinitionError::InvalidValue {
+                    option: "animation".to_owned(),
+                    value: animation.to_owned(),
+                })?;
+
+        self.animation[offset(&definition.report, &definition.animation.index)] = value;
+
+        Ok(self)
+    }
+
+    /// Set the color of the animation. Does nothing if the model has no animation color.
+    pub fn set_animation_color<C: Into<Srgb<u8>>>(
+        mut self,
+        color: C,
+    ) -> Result<Self, DefinitionError> {
+        let definition = self
+            .definition
+            .animation
+            .as_ref()
+            .ok_or(DefinitionError::NoAnimation)?;
+
+        if let Some(index) = &definition.color {
+            let indexes = [0, 1, 2].map(|i| Indexes::new(index.step, index.index + i));
+            set_rgb(
+                &mut self.animation,
+                &definition.report,
+                &indexes,
+                color.into(),
+            );
+        }
+
+        Ok(self)
+    }
+
+    /// Set one of the options of the animation setting, such as `("speed", "three")`.
+    pub fn set_animation_option(
+        mut self,
+        option: &str,
+        value: &str,
+    ) -> Result<Self, DefinitionError> {
+        let definition = self
+            .definition
+            .animation
+            .as_ref()
+            .ok_or(DefinitionError::NoAnimation)?;
+
+        set_option(&mut self.animation, &definition.report, option, value)?;
+
+        Ok(self)
+    }
+
+    /// The reports of the color setting, in the order they are sent.
+    pub fn color_reports(&self) -> impl Iterator<Item = &[u8]> {
+        self.color.chunks_exact(self.definition.color.step_len)
+    }
+
+    /// The reports of the animation setting, in the order they are sent. Empty if the model has
+    /// no animations.
+    pub fn animation_reports(&self) -> impl Iterator<Item = &[u8]> {
+        let step_len = self
+            .definition
+            .animation
+            .as_ref()
+            .map_or(1, |animation| animation.report.step_len);
+
+        self.animation.chunks_exact(step_len)
+    }
+
+    pub fn apply_color(self) -> HidResult<Self> {
+        let device = self.transport.open()?;
+
+        let reports: Vec<_> = self.color_reports().collect();
+        self.write_policy.write(&device, &reports)?;
+
+        device.close()?;
+
+        Ok(self)
+    }
+
+    pub fn apply_animation(self) -> HidResult<Self> {
+        let device = self.transport.open()?;
+
+        let reports: Vec<_> = self.animation_reports().collect();
+        self.write_policy.write(&device, &reports)?;
+
+        device.close()?;
+
+        Ok(self)
+    }
+}
+
+/// Offset of an index that was checked by [`ModelDefinition::validate`].
+fn offset(report: &ReportDefinition, index: &Indexes) -> usize {
+    index.step * report.step_len + index.index
+}
+
+fn set_rgb(data: &mut [u8], report: &ReportDefinition, indexes: &[Indexes; 3], color: Srgb<u8>) {
+    for (index, value) in indexes.iter().zip([color.red, color.green, color.blue]) {
+        data[offset(report, index)] = value;
+    }
+}
+
+fn set_option(
+    data: &mut [u8],
+    report: &ReportDefinition,
+    option: &str,
+    value: &str,
+) -> Result<(), DefinitionError> {
+    let definition = report
+        .options
+        .iter()
+        .find(|o| o.name == option)
+        .ok_or_else(|| DefinitionError::UnknownOption(option.to_owned()))?;
+
+    data[offset(report, &definition.values.index)] =
+        definition
+            .values
+            .value(value)
+            .ok_or_else(|| DefinitionError::InvalidValue {
+                option: option.to_owned(),
+                value: value.to_owned(),
+            })?;
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "profiles"))]
+mod tests {
+    use std::sync::Arc;
+
+    use palette::named;
+
+    use crate::keyboards::{
+        definition::ModelDefinition,
+        rk68::{keys::Keys, Animation, AnimationOptions, Brightness, Rk68, Sleep, Speed},
+        transport::DryRunTransport,
+        KeyboardAnimatable, KeyboardAnimationOption, KeyboardColorOption, KeyboardColorable,
+        KeyboardPerKeyColorable,
+    };
+
+    use super::GenericKeyboard;
+
+    fn rk68() -> Arc<ModelDefinition> {
+        Arc::new(ModelDefinition::from_toml(include_str!("../../models/rk68.toml")).unwrap())
+    }
+
+    /// The definition of the RK68 has to send the same reports as the built in model.
+    #[test]
+    fn test_same_as_rk68() {
+        let generic = DryRunTransport::new();
+        GenericKeyboard::with_transport(rk68(), generic.clone())
+            .set_color(named::BLUE)
+            .set_key_color("num3", named::RED)
+            .unwrap()
+            .set_color_option("sleep", "never")
+            .unwrap()
+            .apply_color()
+            .unwrap()
+            .set_animation("breathing")
+            .unwrap()
+            .set_animation_color(named::GREEN)
+            .unwrap()
+            .set_animation_option("speed", "four")
+            .unwrap()
+            .set_animation_option("brightness", "two")
+            .unwrap()
+            .apply_animation()
+            .unwrap();
+
+        let built_in = DryRunTransport::new();
+        Rk68::with_transport(built_in.clone())
+            .set_color(named::BLUE)
+            .set_key_color(Keys::Num3, named::RED)
+            .set_color_parameters(Sleep::Never)
+            .apply_color()
+            .unwrap()
+            .set_animation(Animation::Breathing)
+            .set_animation_parameters(AnimationOptions {
+                color: named::GREEN,
+                speed: Speed::Four,
+                brightness: Brightness::Two,
+                sleep: Sleep::Never,
+                color_mix: false,
+            })
+            .apply_animation()
+            .unwrap();
+
+        assert_eq!(generic.reports(), built_in.reports());
+    }
+
+    #[test]
+    fn test_unknown_names() {
+        let kb = GenericKeyboard::with_transport(rk68(), DryRunTransport::new());
+
+        assert!(kb.clone().set_key_color("logo", named::RED).is_err());
+        assert!(kb.clone().set_color_option("speed", "one").is_err());
+        assert!(kb.set_animation_option("speed", "six").is_err());
+    }
+}
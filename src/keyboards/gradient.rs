@@ -0,0 +1,266 @@
+use palette::{FromColor, Hsv, Mix, Oklab, Srgb};
+use strum::{EnumString, IntoStaticStr, VariantNames};
+
+use super::{layout::Layout, KeyboardLayout, KeyboardPerKeyColorable};
+
+/// The color space colors are mixed in, when interpolating between gradient stops.
+///
+/// The same two colors can produce quite different gradients depending on the space they are
+/// mixed in. For example mixing red and green in sRGB goes through a muddy brown, while HSV goes
+/// through yellow.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, IntoStaticStr, VariantNames, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Interpolation {
+    Srgb,
+    LinearRgb,
+    #[default]
+    Oklab,
+    Hsv,
+}
+
+impl Interpolation {
+    /// Mix two colors, where a `factor` of `0.0` is `a`, and `1.0` is `b`.
+    pub fn mix(&self, a: Srgb<u8>, b: Srgb<u8>, factor: f32) -> Srgb<u8> {
+        let (a, b): (Srgb<f32>, Srgb<f32>) = (a.into_format(), b.into_format());
+
+        let mixed = match self {
+            Self::Srgb => a.mix(b, factor),
+            Self::LinearRgb => {
+                Srgb::from_linear(a.into_linear::<f32>().mix(b.into_linear::<f32>(), factor))
+            }
+            Self::Oklab => Srgb::from_color(Oklab::from_color(a).mix(Oklab::from_color(b), factor)),
+            Self::Hsv => Srgb::from_color(Hsv::from_color(a).mix(Hsv::from_color(b), factor)),
+        };
+
+        mixed.into_format()
+    }
+}
+
+/// A gradient made out of one, or more color stops.
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    /// Position, and color of each stop. Sorted by position.
+    stops: Vec<(f32, Srgb<u8>)>,
+    interpolation: Interpolation,
+}
+
+impl Gradient {
+    /// Create a gradient with the colors spread evenly from start to end.
+    ///
+    /// # Panics:
+    /// Will cause a panic if no colors are provided.
+    pub fn new<I, C>(colors: I) -> Self
+    where
+        I: IntoIterator<Item = C>,
+        C: Into<Srgb<u8>>,
+    {
+        let colors: Vec<Srgb<u8>> = colors.into_iter().map(Into::into).collect();
+        let last = colors.len().saturating_sub(1).max(1) as f32;
+
+        Self::with_stops(
+            colors
+                .into_iter()
+                .enumerate()
+                .map(|(i, color)| (i as f32 / last, color)),
+        )
+    }
+
+    /// Create a gradient from stops, where each stop is a position from `0.0` to `1.0`, and a
+    /// color.
+    ///
+    /// # Panics:
+    /// Will cause a panic if no stops are provided.
+    pub fn with_stops<I, C>(stops: I) -> Self
+    where
+        I: IntoIterator<Item = (f32, C)>,
+        C: Into<Srgb<u8>>,
+    {
+        let mut stops: Vec<(f32, Srgb<u8>)> = stops
+            .into_iter()
+            .map(|(position, color)| (position.clamp(0.0, 1.0), color.into()))
+            .collect();
+        assert!(!stops.is_empty(), "a gradient needs at least one stop");
+
+        stops.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        Self {
+            stops,
+            interpolation: Interpolation::default(),
+        }
+    }
+
+    /// Set the color space the stops are mixed in.
+    #[inline]
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    /// The color at `position`, where `0.0` is the start, and `1.0` is the end of the gradient.
+    pub fn at(&self, position: f32) -> Srgb<u8> {
+        let position = position.clamp(0.0, 1.0);
+
+        // Ok to index, there is always at least one stop.
+        let end = match self.stops.iter().position(|(p, _)| *p >= position) {
+            Some(0) => return self.stops[0].1,
+            Some(end) => end,
+            None => return self.stops[self.stops.len() - 1].1,
+        };
+
+        let (start_position, start) = self.stops[end - 1];
+        let (end_position, end) = self.stops[end];
+
+        let factor = (position - start_position) / (end_position - start_position);
+
+        self.interpolation.mix(start, end, factor)
+    }
+}
+
+/// How a [`Gradient`] is laid over the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    /// A gradient along a line through the keyboard.
+    ///
+    /// The angle is in degrees, counter-clockwise. `0.0` goes from left to right, and `90.0` goes
+    /// from the bottom to the top.
+    Linear { angle: f32 },
+
+    /// A gradient moving outwards from a point, in key units.
+    ///
+    /// See [`KeyGeometry::center`](super::layout::KeyGeometry::center) to start from a key.
+    Radial { center: (f32, f32) },
+}
+
+/// Distance of a point along a line at `angle` degrees, in the direction of [`Shape::Linear`].
+pub(crate) fn project((x, y): (f32, f32), angle: f32) -> f32 {
+    let (sin, cos) = angle.to_radians().sin_cos();
+
+    // Rows grow downwards, flip the Y axis so that positive angles point up.
+    x * cos - y * sin
+}
+
+/// A [`Gradient`] painted over the keys of a keyboard.
+///
+/// ```
+/// # use kludged::keyboards::{gradient::{Gradient, GradientFill}, rk68::keys::Keys};
+/// # use palette::Srgb;
+/// let gradient = Gradient::new([Srgb::new(255, 0, 0), Srgb::new(0, 0, 255)]);
+/// let fill = GradientFill::linear(gradient, 45.0);
+/// let colors: Vec<_> = fill.colors(&Keys::layout()).collect();
+///
+/// assert_eq!(colors.len(), 68);
+/// ```
+#[derive(Clone, Debug)]
+pub struct GradientFill {
+    pub gradient: Gradient,
+    pub shape: Shape,
+}
+
+impl GradientFill {
+    #[inline]
+    pub fn linear(gradient: Gradient, angle: f32) -> Self {
+        Self {
+            gradient,
+            shape: Shape::Linear { angle },
+        }
+    }
+
+    #[inline]
+    pub fn radial(gradient: Gradient, center: (f32, f32)) -> Self {
+        Self {
+            gradient,
+            shape: Shape::Radial { center },
+        }
+    }
+
+    /// The color of each key in the layout.
+    ///
+    /// The gradient is stretched so that its start, and end land on the centers of the outermost
+    /// keys.
+    pub fn colors<'a, K: Copy + PartialEq>(
+        &'a self,
+        layout: &'a Layout<K>,
+    ) -> impl Iterator<Item = (K, Srgb<u8>)> + 'a {
+        let distance = move |(x, y): (f32, f32)| match self.shape {
+            Shape::Linear { angle } => project((x, y), angle),
+            Shape::Radial { center } => (x - center.0).hypot(y - center.1),
+        };
+
+        let (min, max) = layout
+            .keys()
+            .map(|(_, geometry)| distance(geometry.center()))
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), d| {
+                (min.min(d), max.max(d))
+            });
+
+        // A radial gradient always starts at the center, even if no key is on it.
+        let min = match self.shape {
+            Shape::Linear { .. } => min,
+            Shape::Radial { .. } => 0.0,
+        };
+        let range = (max - min).max(f32::EPSILON);
+
+        layout.keys().map(move |(key, geometry)| {
+            let position = (distance(geometry.center()) - min) / range;
+
+            (key, self.gradient.at(position))
+        })
+    }
+
+    /// Paint the gradient on a keyboard.
+    pub fn paint<KB>(&self, kb: KB) -> KB
+    where
+        KB: KeyboardPerKeyColorable + KeyboardLayout<Key = <KB as KeyboardPerKeyColorable>::Key>,
+        <KB as KeyboardPerKeyColorable>::Key: Copy + PartialEq,
+    {
+        self.colors(&KB::layout())
+            .fold(kb, |kb, (key, color)| kb.set_key_color(key, color))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use palette::Srgb;
+
+    use crate::keyboards::rk68::keys::Keys;
+
+    use super::{Gradient, GradientFill, Interpolation};
+
+    const RED: Srgb<u8> = Srgb::new(255, 0, 0);
+    const BLUE: Srgb<u8> = Srgb::new(0, 0, 255);
+
+    #[test]
+    fn test_gradient_stops() {
+        let gradient = Gradient::new([RED, BLUE]).interpolation(Interpolation::Srgb);
+        assert_eq!(gradient.at(0.0), RED);
+        assert_eq!(gradient.at(1.0), BLUE);
+        assert_eq!(gradient.at(0.5), Srgb::new(128, 0, 128));
+
+        let gradient = Gradient::with_stops([(0.8, BLUE), (0.2, RED)]);
+        assert_eq!(gradient.at(0.1), RED);
+        assert_eq!(gradient.at(0.9), BLUE);
+
+        let gradient = Gradient::new([RED]);
+        assert_eq!(gradient.at(0.5), RED);
+    }
+
+    #[test]
+    fn test_gradient_fill() {
+        let layout = Keys::layout();
+        let gradient = Gradient::new([RED, BLUE]);
+
+        let fill = GradientFill::linear(gradient.clone(), 0.0);
+        let colors: Vec<_> = fill.colors(&layout).collect();
+        assert!(colors.contains(&(Keys::Esc, RED)));
+        assert!(colors.contains(&(Keys::RightArrow, BLUE)));
+
+        let fill = GradientFill::radial(gradient, Keys::G.geometry().center());
+        let colors: Vec<_> = fill.colors(&layout).collect();
+        assert!(colors.contains(&(Keys::G, RED)));
+    }
+}
@@ -0,0 +1,59 @@
+use hidapi::DeviceInfo;
+
+/// A snapshot of what the HID backend reports about a detected keyboard.
+///
+/// Unlike [`DeviceInfo`], this owns all of its data, and can be serialized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub struct KeyboardInfo {
+    /// Name of the model, such as `"rk68"`.
+    pub model: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub interface_number: i32,
+    pub usage_page: u16,
+    pub usage: u16,
+
+    /// Path of the device, such as `/dev/hidraw3` on Linux.
+    pub path: String,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+
+    /// Release number of the device, in binary coded decimal.
+    pub release_number: u16,
+}
+
+impl KeyboardInfo {
+    pub fn new(model: &str, device_info: &DeviceInfo) -> Self {
+        // Some backends report missing strings as empty instead of not reporting them at all.
+        let string = |s: Option<&str>| s.filter(|s| !s.is_empty()).map(str::to_owned);
+
+        Self {
+            model: model.to_owned(),
+            vendor_id: device_info.vendor_id(),
+            product_id: device_info.product_id(),
+            interface_number: device_info.interface_number(),
+            usage_page: device_info.usage_page(),
+            usage: device_info.usage(),
+            path: device_info.path().to_string_lossy().into_owned(),
+            serial_number: string(device_info.serial_number()),
+            manufacturer: string(device_info.manufacturer_string()),
+            product: string(device_info.product_string()),
+            release_number: device_info.release_number(),
+        }
+    }
+
+    /// The release number formatted as a version, such as `"1.04"`.
+    pub fn release(&self) -> String {
+        format!(
+            "{:x}.{:02x}",
+            self.release_number >> 8,
+            self.release_number & 0xff
+        )
+    }
+}
@@ -0,0 +1,124 @@
+use super::steps::Indexes;
+
+/// Where a key physically is on a keyboard.
+///
+/// Positions, and sizes are in key units, where `1.0` is the width of a letter key. The origin
+/// is the top left corner of the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyGeometry {
+    /// The row the key is on, starting from the top.
+    pub row: usize,
+
+    /// The N'th key in the row, starting from the left.
+    pub column: usize,
+
+    /// Distance from the left edge of the keyboard to the left edge of the key.
+    pub x: f32,
+
+    /// Distance from the top edge of the keyboard to the top edge of the key.
+    pub y: f32,
+
+    pub width: f32,
+    pub height: f32,
+}
+
+impl KeyGeometry {
+    /// The center point of the key as `(x, y)`.
+    #[inline]
+    pub fn center(&self) -> (f32, f32) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+}
+
+/// The physical layout of a keyboard.
+///
+/// Stores the [`KeyGeometry`] of every key, in the order the keyboard defines its keys.
+#[derive(Clone, Debug)]
+pub struct Layout<K> {
+    keys: Vec<(K, KeyGeometry)>,
+    width: f32,
+    height: f32,
+}
+
+impl<K: Copy + PartialEq> Layout<K> {
+    pub fn new(keys: Vec<(K, KeyGeometry)>) -> Self {
+        let (width, height) = keys
+            .iter()
+            .fold((0.0_f32, 0.0_f32), |(w, h), (_, geometry)| {
+                (
+                    w.max(geometry.x + geometry.width),
+                    h.max(geometry.y + geometry.height),
+                )
+            });
+
+        Self {
+            keys,
+            width,
+            height,
+        }
+    }
+
+    /// Iterator over every key, and its geometry.
+    #[inline]
+    pub fn keys(&self) -> impl Iterator<Item = (K, &KeyGeometry)> {
+        self.keys.iter().map(|(key, geometry)| (*key, geometry))
+    }
+
+    /// Iterator over the keys in a row, from left to right.
+    pub fn row(&self, row: usize) -> impl Iterator<Item = (K, &KeyGeometry)> {
+        let mut keys: Vec<_> = self.keys().filter(|(_, g)| g.row == row).collect();
+        keys.sort_by_key(|(_, g)| g.column);
+
+        keys.into_iter()
+    }
+
+    /// The geometry of a key, if it is part of the layout.
+    #[inline]
+    pub fn geometry(&self, key: K) -> Option<&KeyGeometry> {
+        self.keys
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, geometry)| geometry)
+    }
+
+    /// Width of the keyboard, in key units.
+    #[inline(always)]
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    /// Height of the keyboard, in key units.
+    #[inline(always)]
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// Where the color of each key is stored, as written to a layout file by `kludged probe`.
+///
+/// Keys are kept in the order their colors are stored in.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyTable {
+    pub keys: Vec<KeyIndexes>,
+}
+
+/// The indexes of the red, green, and blue values of a key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyIndexes {
+    /// The name of the key, in kebab-case.
+    pub key: String,
+    pub indexes: [Indexes; 3],
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use hidapi::{HidError, HidResult};
+
+use super::transport::{Transport, TransportDevice};
+
+/// An in-memory [`Transport`] that records every feature report sent through it.
+///
+/// Clones share the same state, so a clone can be handed to a keyboard while the original is kept
+/// around to inspect what was sent.
+///
+/// ```
+/// # use kludged::keyboards::{mock::MockTransport, rk68::Rk68, KeyboardAnimatable};
+/// let mock = MockTransport::new();
+/// Rk68::with_transport(mock.clone()).apply_animation().unwrap();
+///
+/// assert_eq!(mock.reports().len(), 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    reports: Vec<Vec<u8>>,
+    responses: VecDeque<Vec<u8>>,
+    open_count: usize,
+    disconnected: bool,
+
+    /// Number of sends that succeed before `failures` sends fail.
+    failures_after: usize,
+    failures: usize,
+}
+
+impl MockTransport {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every feature report sent so far, in the order they were sent.
+    pub fn reports(&self) -> Vec<Vec<u8>> {
+        self.state().reports.clone()
+    }
+
+    /// Forget all of the recorded reports.
+    pub fn clear(&self) {
+        self.state().reports.clear();
+    }
+
+    /// Number of times the device was opened.
+    pub fn open_count(&self) -> usize {
+        self.state().open_count
+    }
+
+    /// Queue a report to be returned by the next [`TransportDevice::get_feature_report`] call.
+    ///
+    /// Once the queue is empty, reading a feature report returns an error.
+    pub fn push_response(&self, report: impl Into<Vec<u8>>) {
+        self.state().responses.push_back(report.into());
+    }
+
+    /// Act as if the device was unplugged. Opening, and writing fail until [`Self::reconnect`]
+    /// is called, even for devices that are already open.
+    pub fn disconnect(&self) {
+        self.state().disconnected = true;
+    }
+
+    /// Act as if the device was plugged back in.
+    pub fn reconnect(&self) {
+        self.state().disconnected = false;
+    }
+
+    /// Make sends fail, as if the device did not accept them. After `after` more successful sends,
+    /// the next `count` sends fail. Failed sends are not recorded.
+    pub fn fail_sends(&self, after: usize, count: usize) {
+        let mut state = self.state();
+        state.failures_after = after;
+        state.failures = count;
+    }
+
+    /// The error hidapi returns for a device that is no longer there.
+    fn check_connected(&self) -> HidResult<()> {
+        if self.state().disconnected {
+            return Err(HidError::HidApiError {
+                message: "No such device".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        // A poisoned lock only means a test panicked while holding it, the recorded data is still
+        // valid.
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Transport for MockTransport {
+    type Device = MockDevice;
+
+    fn open(&self) -> HidResult<Self::Device> {
+        self.check_connected()?;
+        self.state().open_count += 1;
+
+        Ok(MockDevice {
+            transport: self.clone(),
+        })
+    }
+}
+
+/// An opened [`MockTransport`].
+#[derive(Debug)]
+pub struct MockDevice {
+    transport: MockTransport,
+}
+
+impl TransportDevice for MockDevice {
+    fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+        self.transport.check_connected()?;
+
+        let mut state = self.transport.state();
+        if state.failures_after > 0 {
+            state.failures_after -= 1;
+        } else if state.failures > 0 {
+            state.failures -= 1;
+
+            return Err(HidError::HidApiError {
+                message: "Input/output error".to_string(),
+            });
+        }
+
+        state.reports.push(data.to_vec());
+
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+        self.transport.check_connected()?;
+
+        let response = self
+            .transport
+            .state()
+            .responses
+            .pop_front()
+            .ok_or_else(|| HidError::HidApiError {
+                message: "No response queued for the mock device.".to_string(),
+            })?;
+
+        let len = response.len().min(buf.len());
+        buf[..len].copy_from_slice(&response[..len]);
+
+        Ok(len)
+    }
+}
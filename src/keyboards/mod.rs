@@ -1,17 +1,48 @@
+pub mod capture;
+pub mod color;
+pub mod definition;
+pub mod effects;
+pub mod generic;
+pub mod gradient;
+pub mod info;
+pub mod layout;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod policy;
 pub mod rk68;
+pub mod selector;
 pub mod steps;
+pub mod transport;
 
-use crate::errors::DeviceNotFound;
+use crate::errors::{DeviceNotFound, ReadError};
 
 use super::errors::GetDeviceError;
+use definition::ModelDefinition;
+use generic::GenericKeyboard;
+use info::KeyboardInfo;
+use layout::Layout;
 use rk68::Rk68;
+use selector::{DeviceSelector, Target};
+
+use std::sync::Arc;
 
 use hidapi::{DeviceInfo, HidApi, HidResult};
 use palette::Srgb;
+use strum::{EnumString, IntoStaticStr, VariantNames};
+
+/// Every supported keyboard model, named the same as its subcommand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, VariantNames, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Model {
+    Rk68,
+}
 
 #[derive(Clone, Debug)]
 pub enum KeyboardModels {
-    Rk68(Rk68),
+    Rk68(Box<Rk68>),
+
+    /// A keyboard described by a [`ModelDefinition`].
+    Generic(Box<GenericKeyboard>),
 }
 
 impl KeyboardModels {
@@ -41,6 +72,64 @@ impl KeyboardModels {
 
         Ok(keyboards)
     }
+
+    /// Same as [`KeyboardModels::keyboards`], but also looks for the models described by
+    /// `definitions`.
+    ///
+    /// Built in models are looked for first, a device is never picked up by a definition if a
+    /// built in model supports it.
+    pub fn keyboards_with_definitions(
+        definitions: &[Arc<ModelDefinition>],
+    ) -> HidResult<Vec<Self>> {
+        let hid_api = HidApi::new()?;
+        let mut keyboards = KeyboardModels::keyboards_from_hidapi(&hid_api)?;
+
+        let mut devices: Vec<_> = hid_api
+            .device_list()
+            .filter(|device_info| {
+                !keyboards
+                    .iter()
+                    .any(|kb| kb.device_info().path() == device_info.path())
+            })
+            .collect();
+
+        for definition in definitions {
+            while let Some(keyboard) = GenericKeyboard::get_from_devices(definition, &mut devices) {
+                keyboards.push(Self::Generic(Box::new(keyboard)));
+            }
+        }
+
+        Ok(keyboards)
+    }
+
+    /// The model of the keyboard, or [`None`] for keyboards described by a definition.
+    pub fn model(&self) -> Option<Model> {
+        match self {
+            Self::Rk68(_) => Some(Model::Rk68),
+            Self::Generic(_) => None,
+        }
+    }
+
+    /// Name of the keyboard model, as used for its subcommand.
+    #[inline]
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Rk68(_) => Model::Rk68.into(),
+            Self::Generic(kb) => &kb.definition().name,
+        }
+    }
+
+    /// Information about the device the keyboard was found on.
+    pub fn info(&self) -> KeyboardInfo {
+        KeyboardInfo::new(self.name(), self.device_info())
+    }
+
+    fn device_info(&self) -> &DeviceInfo {
+        match self {
+            Self::Rk68(kb) => kb.transport().device_info(),
+            Self::Generic(kb) => kb.transport().device_info(),
+        }
+    }
 }
 
 pub trait Specs {
@@ -72,6 +161,60 @@ pub trait Keyboard: Sized + Specs {
         }
     }
 
+    /// Every plugged in keyboard of this model, in the order `kludged list` shows them.
+    fn all() -> HidResult<Vec<Self>> {
+        let mut hid_api = HidApi::new()?;
+
+        // The instance already holds every device, only keep the ones for this model.
+        hid_api.reset_devices()?;
+        hid_api.add_devices(Self::VID, Self::PID)?;
+
+        let mut devices = hid_api.device_list().collect();
+
+        Ok(std::iter::from_fn(|| Self::get_from_devices(&mut devices)).collect())
+    }
+
+    /// The keyboard picked out by `selector`.
+    fn select(selector: &DeviceSelector) -> Result<Self, GetDeviceError> {
+        let kb = match selector {
+            DeviceSelector::Index(index) => Self::all()?.into_iter().nth(*index),
+            selector => {
+                let mut hid_api = HidApi::new()?;
+
+                hid_api.reset_devices()?;
+                hid_api.add_devices(Self::VID, Self::PID)?;
+
+                let mut devices = hid_api
+                    .device_list()
+                    .filter(|device_info| selector.matches(device_info))
+                    .collect();
+
+                Self::get_from_devices(&mut devices)
+            }
+        };
+
+        kb.ok_or(DeviceNotFound.into())
+    }
+
+    /// The keyboards a setting should be written to for `target`.
+    ///
+    /// Never returns an empty [`Vec`], [`DeviceNotFound`] is returned instead.
+    fn from_target(target: &Target) -> Result<Vec<Self>, GetDeviceError> {
+        match target {
+            Target::First => Ok(vec![Self::new()?]),
+            Target::Device(selector) => Ok(vec![Self::select(selector)?]),
+            Target::All => {
+                let keyboards = Self::all()?;
+
+                if keyboards.is_empty() {
+                    Err(DeviceNotFound)?
+                }
+
+                Ok(keyboards)
+            }
+        }
+    }
+
     /// The correct [`DeviceInfo`]\('s) should be removed and used to construct the instance.
     ///
     /// Any other device information should remain untouched. Takes in a mutable [`Vec`] as in some
@@ -88,7 +231,11 @@ pub trait Keyboard: Sized + Specs {
     fn get_from_devices(devices: &mut Vec<&DeviceInfo>) -> Option<Self>;
 }
 
-pub trait KeyboardColorable: Keyboard {
+/// A keyboard whose colors can be set.
+///
+/// Implementations should write through their [`transport::Transport`] rather than opening the
+/// device directly, so that the same code path can be used with any backend.
+pub trait KeyboardColorable: Sized + Specs {
     const COLOR_ENDPOINT: i32;
     const USAGE: u16;
     const USAGE_PAGE: u16;
@@ -97,18 +244,57 @@ pub trait KeyboardColorable: Keyboard {
     fn apply_color(self) -> HidResult<Self>;
 }
 
+/// A keyboard where each key can be given its own color.
+///
+/// Like [`KeyboardColorable::set_color`], the colors are only stored until
+/// [`KeyboardColorable::apply_color`] is called.
+pub trait KeyboardPerKeyColorable: KeyboardColorable {
+    type Key;
+
+    fn set_key_color<C: Into<Srgb<u8>>>(self, key: Self::Key, color: C) -> Self;
+
+    fn set_keys_color<I, C>(self, keys: I, color: C) -> Self
+    where
+        I: IntoIterator<Item = Self::Key>,
+        C: Into<Srgb<u8>>,
+    {
+        let color: Srgb<u8> = color.into();
+
+        keys.into_iter()
+            .fold(self, |kb, key| kb.set_key_color(key, color))
+    }
+
+    /// The color currently stored for a key.
+    fn key_color(&self, key: Self::Key) -> Srgb<u8>;
+}
+
+/// A keyboard with a known physical layout.
+pub trait KeyboardLayout {
+    type Key;
+
+    fn layout() -> Layout<Self::Key>;
+}
+
 pub trait KeyboardColorOption {
     type Options;
     fn set_color_parameters<T: Into<Self::Options>>(self, options: T) -> Self;
 }
 
-pub trait KeyboardAnimatable {
+pub trait KeyboardAnimatable: Sized {
     const ANIMATION_ENDPOINT: i32;
     const USAGE: u16;
     const USAGE_PAGE: u16;
     type Animation;
     fn set_animation(self, animation: Self::Animation) -> Self;
-    fn apply_animation(self) -> HidResult<()>;
+
+    /// Write the stored animation, and get the keyboard back, the same as
+    /// [`KeyboardColorable::apply_color`].
+    ///
+    /// ### Breaking change:
+    /// This used to return `HidResult<()>`, and the trait had no `Sized` bound. Implementations
+    /// now return `Ok(self)` once written, and callers that do not need the keyboard afterwards
+    /// can drop it.
+    fn apply_animation(self) -> HidResult<Self>;
 }
 
 pub trait KeyboardAnimationOption {
@@ -116,8 +302,29 @@ pub trait KeyboardAnimationOption {
     fn set_animation_parameters<T: Into<Self::Options>>(self, options: T) -> Self;
 }
 
+/// A keyboard that can report its current settings.
+///
+/// Unlike the setters of the other traits, reading talks to the keyboard right away.
+pub trait KeyboardReadable: KeyboardAnimatable + KeyboardAnimationOption {
+    /// Ask the keyboard for its current animation, and animation options.
+    fn read_animation(
+        &self,
+    ) -> Result<(Self::Animation, <Self as KeyboardAnimationOption>::Options), ReadError>;
+}
+
+/// Check if a device is on the given interface.
+///
+/// hidapi can not read the interface number of virtual devices, such as a `VirtualRk68`, and
+/// reports it as `-1`. Those are only accepted with the `mock` feature, so discovery never picks
+/// an unknown interface of a real keyboard.
+pub(crate) fn on_interface(device_info: &DeviceInfo, interface: i32) -> bool {
+    let number = device_info.interface_number();
+
+    number == interface || (cfg!(any(test, feature = "mock")) && number == -1)
+}
+
 impl From<Rk68> for KeyboardModels {
     fn from(value: Rk68) -> Self {
-        Self::Rk68(value)
+        Self::Rk68(Box::new(value))
     }
 }
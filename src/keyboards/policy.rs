@@ -0,0 +1,215 @@
+use std::{env, str::FromStr, thread::sleep, time::Duration};
+
+use hidapi::HidResult;
+
+use crate::errors::{is_disconnected, InvalidEnvVar};
+
+use super::transport::TransportDevice;
+
+/// Environment variable for [`WritePolicy::delay`], in milliseconds.
+pub const DELAY_ENV: &str = "KLUDGED_PACKET_DELAY";
+
+/// Environment variable for [`WritePolicy::packet_retries`].
+pub const PACKET_RETRIES_ENV: &str = "KLUDGED_PACKET_RETRIES";
+
+/// Environment variable for [`WritePolicy::backoff`], in milliseconds.
+pub const BACKOFF_ENV: &str = "KLUDGED_RETRY_BACKOFF";
+
+/// Environment variable for [`WritePolicy::transaction_retries`].
+pub const TRANSACTION_RETRIES_ENV: &str = "KLUDGED_TRANSACTION_RETRIES";
+
+/// How the packets of a setting are written to a keyboard.
+///
+/// A setting such as a color is sent as a transaction of one, or more packets. A packet that
+/// fails to send is retried on its own first. If it still fails, the whole transaction is
+/// started over from the first packet, so that the keyboard never ends up with half of a
+/// setting. Nothing is retried once the device is disconnected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WritePolicy {
+    /// Time waited after each packet, to give the keyboard time to process it.
+    pub delay: Duration,
+
+    /// Number of times a single packet is retried, before the transaction is started over.
+    pub packet_retries: u32,
+
+    /// Time waited before the first retry. Doubled after every retry of the same packet.
+    pub backoff: Duration,
+
+    /// Number of times the transaction is started over from the first packet, before giving up.
+    pub transaction_retries: u32,
+}
+
+impl Default for WritePolicy {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(5),
+            packet_retries: 2,
+            backoff: Duration::from_millis(10),
+            transaction_retries: 1,
+        }
+    }
+}
+
+impl WritePolicy {
+    /// The default policy, with any of the `KLUDGED_*` environment variables applied.
+    ///
+    /// Variables that are not set, or empty are ignored. An error is returned for the first one
+    /// that can not be parsed.
+    pub fn from_env() -> Result<Self, InvalidEnvVar> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    fn from_vars<V: Fn(&str) -> Option<String>>(var: V) -> Result<Self, InvalidEnvVar> {
+        let parse = |name| parse_var(&var, name);
+        let default = Self::default();
+
+        Ok(Self {
+            delay: parse(DELAY_ENV)?.map_or(default.delay, Duration::from_millis),
+            packet_retries: parse_var(&var, PACKET_RETRIES_ENV)?.unwrap_or(default.packet_retries),
+            backoff: parse(BACKOFF_ENV)?.map_or(default.backoff, Duration::from_millis),
+            transaction_retries: parse_var(&var, TRANSACTION_RETRIES_ENV)?
+                .unwrap_or(default.transaction_retries),
+        })
+    }
+
+    /// Send every packet of a transaction, following the policy.
+    ///
+    /// Returns the last error if the transaction could not be completed.
+    pub fn write<D: TransportDevice>(&self, device: &D, packets: &[&[u8]]) -> HidResult<()> {
+        let mut retries = 0;
+
+        loop {
+            let result = packets.iter().try_for_each(|packet| {
+                self.send(device, packet)?;
+                sleep(self.delay);
+
+                Ok(())
+            });
+
+            match result {
+                Err(err) if retries < self.transaction_retries && !is_disconnected(&err) => {
+                    retries += 1;
+                    sleep(self.backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn send<D: TransportDevice>(&self, device: &D, packet: &[u8]) -> HidResult<()> {
+        let mut backoff = self.backoff;
+
+        for _ in 0..self.packet_retries {
+            match device.send_feature_report(packet) {
+                Err(err) if !is_disconnected(&err) => {
+                    sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+
+        device.send_feature_report(packet)
+    }
+}
+
+/// Parse the variable `name`, if it is set to anything but whitespace.
+fn parse_var<T: FromStr>(
+    var: impl Fn(&str) -> Option<String>,
+    name: &'static str,
+) -> Result<Option<T>, InvalidEnvVar> {
+    match var(name) {
+        Some(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| InvalidEnvVar { name, value }),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::keyboards::{
+        mock::MockTransport,
+        rk68::{mock::ColorFrame, Rk68},
+        KeyboardColorable,
+    };
+
+    use crate::errors::InvalidEnvVar;
+
+    use super::{WritePolicy, DELAY_ENV, PACKET_RETRIES_ENV};
+
+    const POLICY: WritePolicy = WritePolicy {
+        delay: Duration::ZERO,
+        packet_retries: 0,
+        backoff: Duration::ZERO,
+        transaction_retries: 0,
+    };
+
+    #[test]
+    fn test_packet_retry() {
+        let mock = MockTransport::new();
+        let kb = Rk68::with_transport(mock.clone()).with_write_policy(WritePolicy {
+            packet_retries: 1,
+            ..POLICY
+        });
+
+        mock.fail_sends(3, 1);
+        let kb = kb.apply_color().unwrap();
+        assert_eq!(mock.reports().len(), 7);
+
+        mock.fail_sends(3, 2);
+        assert!(kb.apply_color().is_err());
+    }
+
+    #[test]
+    fn test_transaction_retry() {
+        let mock = MockTransport::new();
+        let kb = Rk68::with_transport(mock.clone()).with_write_policy(WritePolicy {
+            transaction_retries: 1,
+            ..POLICY
+        });
+
+        mock.fail_sends(3, 1);
+        kb.apply_color().unwrap();
+
+        // The transaction is started over from the first step.
+        let reports = mock.reports();
+        assert_eq!(reports.len(), 10);
+        let frame = ColorFrame::from_reports(&reports[3..]).unwrap();
+        assert_eq!(frame.step_counters(), [1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_policy_from_vars() {
+        let vars = |vars: &'static [(&str, &str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        assert_eq!(
+            WritePolicy::from_vars(vars(&[])),
+            Ok(WritePolicy::default())
+        );
+        assert_eq!(
+            WritePolicy::from_vars(vars(&[(DELAY_ENV, " 20 "), (PACKET_RETRIES_ENV, "")])),
+            Ok(WritePolicy {
+                delay: Duration::from_millis(20),
+                ..WritePolicy::default()
+            })
+        );
+        assert_eq!(
+            WritePolicy::from_vars(vars(&[(PACKET_RETRIES_ENV, "-1")])),
+            Err(InvalidEnvVar {
+                name: PACKET_RETRIES_ENV,
+                value: "-1".to_owned(),
+            })
+        );
+    }
+}
@@ -0,0 +1,434 @@
+//! Decode the reports sent to an RK68 back into settings.
+//!
+//! The inverse of what [`Rk68`] sends. Any byte that differs from what kludged would send, or is
+//! not a known value is reported as an [`Issue`], which makes it possible to check captures of the
+//! official application for bytes that are not understood yet.
+//!
+//! ```
+//! # use kludged::keyboards::{rk68::{decode::decode, Rk68, Animation}, transport::DryRunTransport, KeyboardAnimatable};
+//! let transport = DryRunTransport::new();
+//! Rk68::with_transport(transport.clone())
+//!     .set_animation(Animation::Neon)
+//!     .apply_animation()
+//!     .unwrap();
+//!
+//! let decoding = decode(&transport.reports());
+//! assert_eq!(decoding.settings.len(), 1);
+//! assert!(decoding.issues.is_empty());
+//! ```
+
+use std::{fmt::Display, ops::Range};
+
+use palette::Srgb;
+use strum::IntoEnumIterator;
+
+use crate::keyboards::{steps::Steps, transport::DryRunTransport};
+
+use super::{
+    dump::{Field, Packet, PacketKind},
+    keys::Keys,
+    profile::Profile,
+    Animation, AnimationOptions, Brightness, ColorOptions, Rk68, Sleep, Speed, COLOR_DATA_LEN,
+    COLOR_LEN, COLOR_STEP_COUNT,
+};
+
+/// A setting, and the reports it was decoded from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decoded {
+    /// Indexes of the reports the setting was decoded from.
+    pub reports: Range<usize>,
+    pub profile: Profile,
+}
+
+/// Something that does not match what kludged sends. Reports are counted from zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// A report that is neither a color step, nor an animation.
+    UnknownReport { report: usize },
+
+    /// A byte that differs from the one kludged sends.
+    UnexpectedByte {
+        report: usize,
+        offset: usize,
+        field: Field,
+        value: u8,
+        expected: u8,
+    },
+
+    /// A byte that is not one of the known values of its field.
+    InvalidValue {
+        report: usize,
+        offset: usize,
+        field: Field,
+        value: u8,
+    },
+
+    /// A color step sent out of order. Steps are sent in order, starting from the first one.
+    UnexpectedStep {
+        report: usize,
+        step: u8,
+        expected: u8,
+    },
+
+    /// A color setting that ended before all of its steps were sent.
+    IncompleteColor { reports: Range<usize> },
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Reports are counted from one for people.
+        match self {
+            Self::UnknownReport { report } => {
+                write!(
+                    f,
+                    "report {}: not a color step, or an animation",
+                    report + 1
+                )
+            }
+            Self::UnexpectedByte {
+                report,
+                offset,
+                field,
+                value,
+                expected,
+            } => write!(
+                f,
+                "report {}, byte {offset} ({field}): {value:#04x}, kludged sends {expected:#04x}",
+                report + 1
+            ),
+            Self::InvalidValue {
+                report,
+                offset,
+                field,
+                value,
+            } => write!(
+                f,
+                "report {}, byte {offset}: {value:#04x} is not a known {field}",
+                report + 1
+            ),
+            Self::UnexpectedStep {
+                report,
+                step,
+                expected,
+            } => write!(
+                f,
+                "report {}: color step {step}, expected step {expected}",
+                report + 1
+            ),
+            Self::IncompleteColor { reports } => write!(
+                f,
+                "reports {} to {}: color setting is missing steps",
+                reports.start + 1,
+                reports.end
+            ),
+        }
+    }
+}
+
+/// The settings found in a sequence of reports, and anything odd about them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Decoding {
+    pub settings: Vec<Decoded>,
+    pub issues: Vec<Issue>,
+}
+
+/// A color setting that is still being received.
+struct PartialColor {
+    start: usize,
+    steps: Steps<COLOR_DATA_LEN>,
+    next_step: u8,
+}
+
+/// Decode a sequence of reports, each starting with the report ID.
+///
+/// A color setting is only decoded once all of its steps were seen in order. Reports that can not
+/// be decoded are skipped, and reported as an [`Issue`].
+pub fn decode<R: AsRef<[u8]>>(reports: &[R]) -> Decoding {
+    // What kludged sends by default, to compare the bytes without a known meaning against.
+    let expected = Rk68::with_transport(DryRunTransport::new());
+
+    let mut decoding = Decoding::default();
+    let mut color: Option<PartialColor> = None;
+
+    for (i, report) in reports.iter().enumerate() {
+        let report = report.as_ref();
+        let packet = Packet::new(report);
+
+        match packet.kind() {
+            PacketKind::Color { step } => {
+                let next_step = color.as_ref().map_or(1, |color| color.next_step);
+
+                if step != next_step {
+                    if let Some(color) = color.take() {
+                        decoding.issues.push(Issue::IncompleteColor {
+                            reports: color.start..i,
+                        });
+                    }
+
+                    if step != 1 {
+                        decoding.issues.push(Issue::UnexpectedStep {
+                            report: i,
+                            step,
+                            expected: next_step,
+                        });
+                        continue;
+                    }
+                }
+
+                let partial = color.get_or_insert_with(|| PartialColor {
+                    start: i,
+                    steps: Steps::new(COLOR_STEP_COUNT, COLOR_LEN, &[]),
+                    next_step: 1,
+                });
+
+                let index = usize::from(step) - 1;
+                partial.steps.nth_step_mut(index).copy_from_slice(report);
+                partial.next_step += 1;
+
+                check_bytes(
+                    &packet,
+                    report,
+                    expected.color_steps.nth_step(index),
+                    i,
+                    &mut decoding.issues,
+                );
+
+                if index + 1 == COLOR_STEP_COUNT {
+                    // Ok to unwrap, it was inserted above.
+                    let partial = color.take().unwrap();
+                    let profile = decode_color(&partial, &mut decoding.issues);
+
+                    decoding.settings.push(Decoded {
+                        reports: partial.start..i + 1,
+                        profile,
+                    });
+                }
+            }
+            PacketKind::Animation => {
+                if let Some(color) = color.take() {
+                    decoding.issues.push(Issue::IncompleteColor {
+                        reports: color.start..i,
+                    });
+                }
+
+                check_bytes(
+                    &packet,
+                    report,
+                    &expected.animation_steps,
+                    i,
+                    &mut decoding.issues,
+                );
+
+                if let Some(profile) = decode_animation(report, i, &mut decoding.issues) {
+                    decoding.settings.push(Decoded {
+                        reports: i..i + 1,
+                        profile,
+                    });
+                }
+            }
+            PacketKind::Unknown => decoding.issues.push(Issue::UnknownReport { report: i }),
+        }
+    }
+
+    if let Some(color) = color {
+        decoding.issues.push(Issue::IncompleteColor {
+            reports: color.start..reports.len(),
+        });
+    }
+
+    decoding
+}
+
+/// Compare the bytes without a setting of their own against the ones kludged sends.
+fn check_bytes(
+    packet: &Packet,
+    report: &[u8],
+    expected: &[u8],
+    index: usize,
+    issues: &mut Vec<Issue>,
+) {
+    for segment in packet.segments() {
+        if !matches!(
+            segment.field,
+            Field::Header | Field::StepCounter | Field::Unknown | Field::Unused
+        ) {
+            continue;
+        }
+
+        for offset in segment.range.clone() {
+            let (value, expected) = (report[offset], expected.get(offset).copied().unwrap_or(0));
+
+            if value != expected {
+                issues.push(Issue::UnexpectedByte {
+                    report: index,
+                    offset,
+                    field: segment.field,
+                    value,
+                    expected,
+                });
+            }
+        }
+    }
+}
+
+fn decode_color(color: &PartialColor, issues: &mut Vec<Issue>) -> Profile {
+    let sleep = color.steps.data[5];
+    let sleep = Sleep::from_repr(sleep).unwrap_or_else(|| {
+        issues.push(Issue::InvalidValue {
+            report: color.start,
+            offset: 5,
+            field: Field::Sleep,
+            value: sleep,
+        });
+
+        Sleep::default()
+    });
+
+    // Keys that are turned off are left out, the same as in a profile that is written by hand.
+    let colors = Keys::iter()
+        .map(|key| {
+            let [red, green, blue] = key.indexes().map(|index| color.steps[index]);
+
+            (key, Srgb::new(red, green, blue))
+        })
+        .filter(|(_, color)| *color != Srgb::new(0, 0, 0));
+
+    Profile::colors(colors, ColorOptions { sleep })
+}
+
+fn decode_animation(report: &[u8], index: usize, issues: &mut Vec<Issue>) -> Option<Profile> {
+    let mut invalid = |offset: usize, field: Field| {
+        issues.push(Issue::InvalidValue {
+            report: index,
+            offset,
+            field,
+            value: report[offset],
+        });
+    };
+
+    let animation = Animation::from_repr(report[5]);
+    if animation.is_none() {
+        invalid(5, Field::Animation);
+    }
+
+    let speed = Speed::from_repr(report[7]).unwrap_or_else(|| {
+        invalid(7, Field::Speed);
+        Speed::default()
+    });
+    let brightness = Brightness::from_repr(report[8]).unwrap_or_else(|| {
+        invalid(8, Field::Brightness);
+        Brightness::default()
+    });
+    if report[12] > 1 {
+        invalid(12, Field::ColorMix);
+    }
+    let sleep = Sleep::from_repr(report[13]).unwrap_or_else(|| {
+        invalid(13, Field::Sleep);
+        Sleep::default()
+    });
+
+    Some(Profile::Animation {
+        animation: animation?,
+        options: AnimationOptions {
+            color_mix: report[12] != 0,
+            color: Srgb::new(report[9], report[10], report[11]),
+            speed,
+            sleep,
+            brightness,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use palette::Srgb;
+
+    use crate::keyboards::{
+        rk68::{dump::Field, keys::Keys, profile::Profile, ColorOptions, Rk68, Sleep},
+        transport::DryRunTransport,
+    };
+
+    use super::{decode, Issue};
+
+    fn reports(profile: &Profile) -> Vec<Vec<u8>> {
+        let transport = DryRunTransport::new();
+        profile
+            .apply(Rk68::with_transport(transport.clone()))
+            .unwrap();
+
+        transport.reports()
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        let color = Profile::colors(
+            [
+                (Keys::Esc, Srgb::new(255, 0, 0)),
+                (Keys::N, Srgb::new(1, 2, 3)),
+            ],
+            ColorOptions {
+                sleep: Sleep::Never,
+            },
+        );
+        let animation = Profile::Animation {
+            animation: super::Animation::Neon,
+            options: Default::default(),
+        };
+
+        let mut all = reports(&color);
+        all.extend(reports(&animation));
+
+        let decoding = decode(&all);
+        assert_eq!(decoding.issues, []);
+        assert_eq!(decoding.settings.len(), 2);
+        assert_eq!(decoding.settings[0].profile, color);
+        assert_eq!(decoding.settings[0].reports, 0..7);
+        assert_eq!(decoding.settings[1].profile, animation);
+    }
+
+    #[test]
+    fn test_decode_issues() {
+        let mut all = reports(&Profile::color(
+            Srgb::new(0, 0, 255),
+            ColorOptions::default(),
+        ));
+
+        // An undocumented byte in the header.
+        all[0][3] = 0x04;
+        // A step that never arrives.
+        all.remove(3);
+        all.push(vec![0x01, 0x02]);
+
+        let decoding = decode(&all);
+        assert!(decoding.settings.is_empty());
+        assert_eq!(
+            decoding.issues,
+            [
+                Issue::UnexpectedByte {
+                    report: 0,
+                    offset: 3,
+                    field: Field::Unknown,
+                    value: 0x04,
+                    expected: 0x03,
+                },
+                Issue::IncompleteColor { reports: 0..3 },
+                Issue::UnexpectedStep {
+                    report: 3,
+                    step: 5,
+                    expected: 4,
+                },
+                Issue::UnexpectedStep {
+                    report: 4,
+                    step: 6,
+                    expected: 1,
+                },
+                Issue::UnexpectedStep {
+                    report: 5,
+                    step: 7,
+                    expected: 1,
+                },
+                Issue::UnknownReport { report: 6 },
+            ]
+        );
+    }
+}
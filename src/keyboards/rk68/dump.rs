@@ -0,0 +1,306 @@
+//! Annotated hex dumps of the packets sent to an RK68.
+//!
+//! Meant for reviewing protocol changes, and comparing what kludged sends against captures of the
+//! official application. Combine with a [`DryRunTransport`](crate::keyboards::transport::DryRunTransport)
+//! to dump a setting without a keyboard being plugged in.
+//!
+//! ```
+//! # use kludged::keyboards::{rk68::{dump::Packet, Rk68}, transport::DryRunTransport, KeyboardAnimatable};
+//! let transport = DryRunTransport::new();
+//! Rk68::with_transport(transport.clone()).apply_animation().unwrap();
+//!
+//! for report in transport.reports() {
+//!     println!("{}", Packet::new(&report));
+//! }
+//! ```
+
+use std::{fmt::Display, ops::Range};
+
+use strum::IntoEnumIterator;
+
+use super::{keys::Keys, Animation, Brightness, Sleep, Speed, COLOR_LEN};
+
+/// What kind of setting a packet belongs to, based on its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketKind {
+    /// One of the steps of a color setting, counting from one.
+    Color {
+        step: u8,
+    },
+    Animation,
+    Unknown,
+}
+
+/// What a range of bytes in a packet is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Header,
+
+    /// The number of a color step.
+    StepCounter,
+
+    /// Bytes the official application sends, with an unknown meaning.
+    Unknown,
+    Sleep,
+
+    /// The color of a key, or part of it for keys that are split over two steps.
+    Key(Keys),
+    Animation,
+    Speed,
+    Brightness,
+    Color,
+    ColorMix,
+
+    /// Bytes that are not used by the setting.
+    Unused,
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Header => "header",
+            Self::StepCounter => "step counter",
+            Self::Unknown => "unknown",
+            Self::Sleep => "sleep",
+            Self::Key(key) => (*key).into(),
+            Self::Animation => "animation",
+            Self::Speed => "speed",
+            Self::Brightness => "brightness",
+            Self::Color => "color",
+            Self::ColorMix => "color mix",
+            Self::Unused => "unused",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// A range of bytes in a packet, and what they are used for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub range: Range<usize>,
+    pub field: Field,
+}
+
+/// A single report, split into the fields of the setting it belongs to.
+#[derive(Clone, Debug)]
+pub struct Packet<'a> {
+    report: &'a [u8],
+    kind: PacketKind,
+    segments: Vec<Segment>,
+}
+
+impl<'a> Packet<'a> {
+    /// Split a report into fields. The report starts with the report ID, as it is sent.
+    ///
+    /// Reports that are not recognized are kept as a single [`Field::Unknown`] segment.
+    pub fn new(report: &'a [u8]) -> Self {
+        let kind = match report {
+            [0x0A, 0x07, step, ..] if report.len() == COLOR_LEN => {
+                PacketKind::Color { step: *step }
+            }
+            [0x0A, 0x01, ..] if report.len() > 14 => PacketKind::Animation,
+            _ => PacketKind::Unknown,
+        };
+
+        let mut fields = vec![Field::Unused; report.len()];
+        match kind {
+            PacketKind::Color { step } => {
+                fields[..2].fill(Field::Header);
+                fields[2] = Field::StepCounter;
+
+                // The settings that are shared by each step are only sent in the first one.
+                if step == 1 {
+                    fields[3..5].fill(Field::Unknown);
+                    fields[5] = Field::Sleep;
+                }
+
+                for key in Keys::iter() {
+                    for index in key.indexes() {
+                        if index.step + 1 == usize::from(step) {
+                            fields[index.index] = Field::Key(key);
+                        }
+                    }
+                }
+            }
+            PacketKind::Animation => {
+                fields[..5].fill(Field::Header);
+                fields[5] = Field::Animation;
+                fields[6] = Field::Unknown;
+                fields[7] = Field::Speed;
+                fields[8] = Field::Brightness;
+                fields[9..12].fill(Field::Color);
+                fields[12] = Field::ColorMix;
+                fields[13] = Field::Sleep;
+            }
+            PacketKind::Unknown => fields.fill(Field::Unknown),
+        }
+
+        // Merge neighbouring bytes of the same field.
+        let mut segments: Vec<Segment> = vec![];
+        for (i, field) in fields.into_iter().enumerate() {
+            match segments.last_mut() {
+                Some(last) if last.field == field => last.range.end = i + 1,
+                _ => segments.push(Segment {
+                    range: i..i + 1,
+                    field,
+                }),
+            }
+        }
+
+        Self {
+            report,
+            kind,
+            segments,
+        }
+    }
+
+    #[inline(always)]
+    pub fn kind(&self) -> PacketKind {
+        self.kind
+    }
+
+    #[inline(always)]
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// A description of the bytes of a segment, such as `"sleep: ten-minutes"`.
+    fn describe(&self, segment: &Segment) -> String {
+        let bytes = &self.report[segment.range.clone()];
+        let byte = bytes[0];
+
+        fn name<T: Into<&'static str>>(value: Option<T>) -> &'static str {
+            value.map_or("invalid", Into::into)
+        }
+
+        match segment.field {
+            Field::Header => "header".to_owned(),
+            Field::StepCounter => format!("step {byte}"),
+            Field::Unknown => "unknown".to_owned(),
+            Field::Sleep => format!("sleep: {}", name(Sleep::from_repr(byte))),
+            Field::Key(key) => {
+                let name: &'static str = key.into();
+
+                // Keys split over two steps only have some of their channels in this packet.
+                let channels: Vec<_> = key
+                    .indexes()
+                    .iter()
+                    .zip(["red", "green", "blue"])
+                    .filter(|(index, _)| {
+                        let step = match self.kind {
+                            PacketKind::Color { step } => usize::from(step),
+                            _ => 0,
+                        };
+
+                        index.step + 1 == step && segment.range.contains(&index.index)
+                    })
+                    .map(|(_, channel)| channel)
+                    .collect();
+
+                if channels.len() == 3 {
+                    name.to_owned()
+                } else {
+                    format!("{name} ({})", channels.join(", "))
+                }
+            }
+            Field::Animation => format!("animation: {}", name(Animation::from_repr(byte))),
+            Field::Speed => format!("speed: {}", name(Speed::from_repr(byte))),
+            Field::Brightness => format!("brightness: {}", name(Brightness::from_repr(byte))),
+            Field::Color => "color".to_owned(),
+            Field::ColorMix => format!("color mix: {}", if byte == 0 { "off" } else { "on" }),
+            Field::Unused => "unused".to_owned(),
+        }
+    }
+}
+
+impl Display for Packet<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            PacketKind::Color { step } => writeln!(f, "color step {step}")?,
+            PacketKind::Animation => writeln!(f, "animation")?,
+            PacketKind::Unknown => writeln!(f, "unknown packet")?,
+        }
+
+        for segment in &self.segments {
+            let Range { start, end } = segment.range;
+            let bytes = &self.report[start..end];
+
+            let offset = if end - start == 1 {
+                format!("{start:>2}")
+            } else {
+                format!("{start:>2}..{end}")
+            };
+
+            // Long runs of zeros are common, and not worth printing byte by byte.
+            let hex = if bytes.len() > 4 && bytes.iter().all(|byte| *byte == 0) {
+                format!("00 x{}", bytes.len())
+            } else {
+                bytes
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+
+            writeln!(f, "  {offset:<6}  {hex:<14}  {}", self.describe(segment))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keyboards::{
+        rk68::{keys::Keys, Rk68},
+        transport::DryRunTransport,
+        KeyboardAnimatable, KeyboardColorable, KeyboardPerKeyColorable,
+    };
+
+    use super::{Field, Packet, PacketKind, Segment};
+
+    #[test]
+    fn test_color_packets() {
+        let transport = DryRunTransport::new();
+        Rk68::with_transport(transport.clone())
+            .set_key_color(Keys::Esc, (255, 0, 0))
+            .apply_color()
+            .unwrap();
+
+        let reports = transport.reports();
+        let first = Packet::new(&reports[0]);
+        assert_eq!(first.kind(), PacketKind::Color { step: 1 });
+        assert!(first.segments().contains(&Segment {
+            range: 9..12,
+            field: Field::Key(Keys::Esc),
+        }));
+
+        let dump = first.to_string();
+        assert!(dump.contains("   9..12  ff 00 00        esc\n"));
+        assert!(dump.contains("sleep: ten-minutes"));
+        assert!(dump.contains("num3 (red, green)"));
+
+        // Keys split over two steps continue at the start of the next step.
+        let second = Packet::new(&reports[1]).to_string();
+        assert!(second.contains("num3 (blue)"));
+        assert!(!second.contains("sleep"));
+    }
+
+    #[test]
+    fn test_animation_packet() {
+        let transport = DryRunTransport::new();
+        Rk68::with_transport(transport.clone())
+            .apply_animation()
+            .unwrap();
+
+        let reports = transport.reports();
+        let packet = Packet::new(&reports[0]);
+        assert_eq!(packet.kind(), PacketKind::Animation);
+
+        let dump = packet.to_string();
+        assert!(dump.contains("animation: neon-stream"));
+        assert!(dump.contains("sleep: never"));
+        assert!(dump.contains("00 x51"));
+    }
+}
@@ -1,89 +1,357 @@
-use crate::keyboards::steps::{same_step_indexes, Indexes};
-use strum::EnumIter;
-
-#[rustfmt::skip]
-#[derive(EnumIter)]
-pub enum Keys {
-    Esc, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, Num0, Dash, Equals, BackSpace, Tilde,
-    Tab, Q, W, E, R, T, Y, U, I, O, P, OpenAngleBracket, CloseAngleBracket, Backslash, Del,
-    CpsLock, A, S, D, F, G, H, J, K, L, SemiColon, Apostrophe, Enter, PgUp,
-    LeftShift, Z, X, C, V, B, N, M, Comma, Period, Slash, RightShift, UpArrow, PgDown,
-    LeftCtrl, Super, LeftAlt, Space, RightAlt, Function, RightCtrl, LeftArrow, DownArrow, RightArrow,
+use std::str::FromStr;
+
+use crate::{errors::InvalidSelection, keyboards::layout::Layout};
+use strum::{EnumIter, EnumString, IntoEnumIterator, IntoStaticStr, VariantNames};
+
+kludged_macros::layout! {
+    /// Every key on the keyboard.
+    ///
+    /// Keys are named in kebab-case when parsed from, or converted to a string, such as `"esc"`,
+    /// `"num1"`, or `"left-shift"`. Parsing is case insensitive.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, EnumIter)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(rename_all = "kebab-case")
+    )]
+    pub enum Keys;
+
+    // The first step also carries the color options, before any of the colors.
+    steps { count: 7, len: 65, first: 6, rest: 3 }
+
+    row { Esc, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, Num0, Dash, Equals, BackSpace: 2.0, Tilde }
+    row { Tab: 1.5, Q, W, E, R, T, Y, U, I, O, P, OpenAngleBracket, CloseAngleBracket, Backslash: 1.5, Del }
+    row { CpsLock: 1.75, A, S, D, F, G, H, J, K, L, SemiColon, Apostrophe, Enter: 2.25, PgUp }
+    row { LeftShift: 2.25, Z, X, C, V, B, N, M, Comma, Period, Slash, RightShift: 1.75, UpArrow, PgDown }
+    row { LeftCtrl: 1.25, Super: 1.25, LeftAlt: 1.25, Space: 6.25, RightAlt, Function, RightCtrl, LeftArrow, DownArrow, RightArrow }
+
+    step 0 {
+        9: Esc, Tab, CpsLock, LeftShift, LeftCtrl;
+        27: Num1, Q, A, Z, Super;
+        45: Num2, W, S, X, LeftAlt;
+        63: Num3;
+    }
+    step 1 {
+        4: E, D, C;
+        19: Num4, R, F, V;
+        37: Num5, T, G, B, Space;
+        55: Num6, Y, H, N;
+    }
+    step 2 {
+        11: Num7, U, J, M;
+        29: Num8, I, K, Comma, RightAlt;
+        47: Num9, O, L, Period, Function;
+    }
+    step 3 {
+        3: Num0, P, SemiColon, Slash, RightCtrl;
+        21: Dash, OpenAngleBracket, Apostrophe, RightShift;
+        39: Equals, CloseAngleBracket;
+        57: BackSpace, Backslash, Enter;
+    }
+    step 4 {
+        7: LeftArrow;
+        22: UpArrow, DownArrow;
+        31: Tilde, Del, PgUp, PgDown, RightArrow;
+    }
 }
 
 impl Keys {
-    // NOTE: This works well. However ideally this should be re-done with a macro to avoid quite a bit of
-    // duplication.
-    pub const fn indexes(&self) -> [Indexes; 3] {
+    /// The physical layout of the keyboard.
+    pub fn layout() -> Layout<Self> {
+        Layout::new(Self::iter().map(|key| (key, key.geometry())).collect())
+    }
+}
+
+/// Named groups of keys.
+///
+/// Like [`Keys`], groups are named in kebab-case and parsing is case insensitive.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter, EnumString, IntoStaticStr, VariantNames,
+)]
+#[strum(serialize_all = "kebab-case", ascii_case_insensitive)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum KeyGroup {
+    All,
+    NumberRow,
+    QwertyRow,
+    HomeRow,
+    /// The row starting with left shift.
+    BottomRow,
+    /// The row containing space.
+    SpaceRow,
+    Alphas,
+    Digits,
+    Modifiers,
+    Arrows,
+    /// Delete, page up, page down and the arrow keys.
+    Navigation,
+    Wasd,
+}
+
+impl KeyGroup {
+    /// The keys in the group.
+    #[rustfmt::skip]
+    pub fn keys(&self) -> Vec<Keys> {
+        use Keys::*;
         match self {
-            Self::Esc => same_step_indexes(0, 9),
-            Self::Tab => same_step_indexes(0, 12),
-            Self::CpsLock => same_step_indexes(0, 15),
-            Self::LeftShift => same_step_indexes(0, 18),
-            Self::LeftCtrl => same_step_indexes(0, 21),
-            Self::Num1 => same_step_indexes(0, 27),
-            Self::Q => same_step_indexes(0, 30),
-            Self::A => same_step_indexes(0, 33),
-            Self::Z => same_step_indexes(0, 36),
-            Self::Super => same_step_indexes(0, 39),
-            Self::Num2 => same_step_indexes(0, 45),
-            Self::W => same_step_indexes(0, 48),
-            Self::S => same_step_indexes(0, 51),
-            Self::X => same_step_indexes(0, 54),
-            Self::LeftAlt => same_step_indexes(0, 57),
-            Self::Num3 => [Indexes::new(0, 63), Indexes::new(0, 64), Indexes::new(1, 3)],
-            Self::E => same_step_indexes(1, 4),
-            Self::D => same_step_indexes(1, 7),
-            Self::C => same_step_indexes(1, 10),
-            Self::Num4 => same_step_indexes(1, 19),
-            Self::R => same_step_indexes(1, 22),
-            Self::F => same_step_indexes(1, 25),
-            Self::V => same_step_indexes(1, 28),
-            Self::Num5 => same_step_indexes(1, 37),
-            Self::T => same_step_indexes(1, 40),
-            Self::G => same_step_indexes(1, 43),
-            Self::B => same_step_indexes(1, 46),
-            Self::Space => same_step_indexes(1, 49),
-            Self::Num6 => same_step_indexes(1, 55),
-            Self::Y => same_step_indexes(1, 58),
-            Self::H => same_step_indexes(1, 61),
-            Self::N => [Indexes::new(1, 64), Indexes::new(2, 3), Indexes::new(2, 4)],
-            Self::Num7 => same_step_indexes(2, 11),
-            Self::U => same_step_indexes(2, 14),
-            Self::J => same_step_indexes(2, 17),
-            Self::M => same_step_indexes(2, 20),
-            Self::Num8 => same_step_indexes(2, 29),
-            Self::I => same_step_indexes(2, 32),
-            Self::K => same_step_indexes(2, 35),
-            Self::Comma => same_step_indexes(2, 38),
-            Self::RightAlt => same_step_indexes(2, 41),
-            Self::Num9 => same_step_indexes(2, 47),
-            Self::O => same_step_indexes(2, 50),
-            Self::L => same_step_indexes(2, 53),
-            Self::Period => same_step_indexes(2, 56),
-            Self::Function => same_step_indexes(2, 59),
-            Self::Num0 => same_step_indexes(3, 3),
-            Self::P => same_step_indexes(3, 6),
-            Self::SemiColon => same_step_indexes(3, 9),
-            Self::Slash => same_step_indexes(3, 12),
-            Self::RightCtrl => same_step_indexes(3, 15),
-            Self::Dash => same_step_indexes(3, 21),
-            Self::OpenAngleBracket => same_step_indexes(3, 24),
-            Self::Apostrophe => same_step_indexes(3, 27),
-            Self::RightShift => same_step_indexes(3, 30),
-            Self::Equals => same_step_indexes(3, 39),
-            Self::CloseAngleBracket => same_step_indexes(3, 42),
-            Self::BackSpace => same_step_indexes(3, 57),
-            Self::Backslash => same_step_indexes(3, 60),
-            Self::Enter => [Indexes::new(3, 63), Indexes::new(3, 64), Indexes::new(4, 3)],
-            Self::LeftArrow => same_step_indexes(4, 7),
-            Self::UpArrow => same_step_indexes(4, 22),
-            Self::DownArrow => same_step_indexes(4, 25),
-            Self::Tilde => same_step_indexes(4, 31),
-            Self::Del => same_step_indexes(4, 34),
-            Self::PgUp => same_step_indexes(4, 37),
-            Self::PgDown => same_step_indexes(4, 40),
-            Self::RightArrow => same_step_indexes(4, 43),
+            Self::All => Keys::iter().collect(),
+            Self::NumberRow => Keys::ROWS[0].to_vec(),
+            Self::QwertyRow => Keys::ROWS[1].to_vec(),
+            Self::HomeRow => Keys::ROWS[2].to_vec(),
+            Self::BottomRow => Keys::ROWS[3].to_vec(),
+            Self::SpaceRow => Keys::ROWS[4].to_vec(),
+            Self::Alphas => vec![
+                A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+            ],
+            Self::Digits => vec![Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, Num0],
+            Self::Modifiers => vec![
+                LeftShift, RightShift, LeftCtrl, RightCtrl, LeftAlt, RightAlt, Super, Function,
+            ],
+            Self::Arrows => vec![UpArrow, LeftArrow, DownArrow, RightArrow],
+            Self::Navigation => vec![Del, PgUp, PgDown, UpArrow, LeftArrow, DownArrow, RightArrow],
+            Self::Wasd => vec![W, A, S, D],
+        }
+    }
+}
+
+/// A set of keys parsed from a selection.
+///
+/// A selection is a comma separated list of terms, evaluated from left to right. A term can be:
+/// - A key name such as `esc`, or a [`KeyGroup`] name such as `alphas`, which adds the keys.
+/// - A range along a row such as `q..p`, which adds both keys and every key between them.
+/// - Any of the above prefixed with `-` such as `-wasd`, which removes the keys.
+///
+/// If the selection starts with a removal, it is applied to every key. In other words `-wasd` is
+/// every key except W, A, S, and D.
+///
+/// ```
+/// # use kludged::keyboards::rk68::keys::{KeySelection, Keys};
+/// let selection: KeySelection = "alphas,-wasd".parse().unwrap();
+///
+/// assert_eq!(selection.len(), 22);
+/// assert!(!selection.contains(Keys::W));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeySelection {
+    keys: Vec<Keys>,
+}
+
+impl KeySelection {
+    #[inline(always)]
+    pub fn keys(&self) -> &[Keys] {
+        &self.keys
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    #[inline]
+    pub fn contains(&self, key: Keys) -> bool {
+        self.keys.contains(&key)
+    }
+
+    /// Resolve a single term, without the removal prefix.
+    fn term(term: &str) -> Result<Vec<Keys>, InvalidSelection> {
+        if let Some((start, end)) = term.split_once("..") {
+            let (start, end) = (Self::key(start.trim())?, Self::key(end.trim())?);
+
+            let row = Keys::ROWS
+                .iter()
+                .find(|row| row.contains(&start) && row.contains(&end))
+                .ok_or_else(|| InvalidSelection::RangeNotInRow(term.to_string()))?;
+
+            // Ok to unwrap, the row is known to contain both keys.
+            let start = row.iter().position(|key| *key == start).unwrap();
+            let end = row.iter().position(|key| *key == end).unwrap();
+
+            return Ok(row[start.min(end)..=start.max(end)].to_vec());
+        }
+
+        if let Ok(group) = KeyGroup::from_str(term) {
+            return Ok(group.keys());
+        }
+
+        Ok(vec![Self::key(term)?])
+    }
+
+    fn key(name: &str) -> Result<Keys, InvalidSelection> {
+        Keys::from_str(name).map_err(|_| InvalidSelection::UnknownKey(name.to_string()))
+    }
+}
+
+impl FromStr for KeySelection {
+    type Err = InvalidSelection;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut selected: Option<Vec<bool>> = None;
+
+        for term in s.split(',').map(str::trim).filter(|term| !term.is_empty()) {
+            let (remove, term) = match term.strip_prefix('-') {
+                Some(term) => (true, term.trim()),
+                None => (false, term),
+            };
+
+            let selected = selected.get_or_insert_with(|| vec![remove; Keys::VARIANTS.len()]);
+            for key in Self::term(term)? {
+                selected[key as usize] = !remove;
+            }
         }
+
+        let selected = selected.ok_or(InvalidSelection::Empty)?;
+        let keys: Vec<Keys> = Keys::iter().filter(|key| selected[*key as usize]).collect();
+
+        if keys.is_empty() {
+            return Err(InvalidSelection::Empty);
+        }
+
+        Ok(Self { keys })
+    }
+}
+
+impl From<Keys> for KeySelection {
+    fn from(value: Keys) -> Self {
+        Self { keys: vec![value] }
+    }
+}
+
+impl From<KeyGroup> for KeySelection {
+    fn from(value: KeyGroup) -> Self {
+        let mut keys = value.keys();
+        keys.sort_by_key(|key| *key as usize);
+
+        Self { keys }
+    }
+}
+
+impl IntoIterator for KeySelection {
+    type Item = Keys;
+    type IntoIter = std::vec::IntoIter<Keys>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::{IntoEnumIterator, VariantNames};
+
+    use crate::{errors::InvalidSelection, keyboards::rk68::color_slots};
+
+    use super::{KeyGroup, KeySelection, Keys};
+
+    #[test]
+    fn test_rows_cover_every_key() {
+        let mut keys: Vec<Keys> = Keys::ROWS.concat();
+        keys.sort_by_key(|key| *key as usize);
+
+        assert_eq!(keys, Keys::iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_keys_use_color_slots() {
+        let slots = color_slots();
+        let mut used: Vec<usize> = Keys::iter()
+            .map(|key| {
+                slots
+                    .iter()
+                    .position(|slot| *slot == key.indexes())
+                    .unwrap()
+            })
+            .collect();
+
+        used.sort();
+        used.dedup();
+        assert_eq!(used.len(), Keys::iter().count());
+    }
+
+    #[test]
+    fn test_names() {
+        for key in Keys::iter() {
+            assert_eq!(key.to_string().parse::<Keys>(), Ok(key));
+            assert_eq!(key.name().to_uppercase().parse::<Keys>(), Ok(key));
+        }
+
+        assert_eq!(Keys::LeftShift.to_string(), "left-shift");
+        assert_eq!(<&str>::from(Keys::Num1), "num1");
+        assert_eq!(Keys::VARIANTS[13], "back-space");
+        assert!("escc".parse::<Keys>().is_err());
+    }
+
+    #[test]
+    fn test_geometry() {
+        for row in Keys::ROWS {
+            // Ok to unwrap, no row is empty.
+            let last = row.last().unwrap().geometry();
+            assert_eq!(last.x + last.width, 16.0);
+        }
+
+        let layout = Keys::layout();
+        assert_eq!((layout.width(), layout.height()), (16.0, 5.0));
+
+        let space = Keys::Space.geometry();
+        assert_eq!((space.row, space.column), (4, 3));
+        assert_eq!((space.x, space.width), (3.75, 6.25));
+
+        let enter = Keys::Enter.geometry();
+        assert_eq!((enter.x, enter.y, enter.width), (12.75, 2.0, 2.25));
+    }
+
+    #[test]
+    fn test_selection() {
+        let selection: KeySelection = "Esc, wasd".parse().unwrap();
+        assert_eq!(
+            selection.keys(),
+            [Keys::Esc, Keys::W, Keys::A, Keys::S, Keys::D]
+        );
+
+        let selection: KeySelection = "q..p".parse().unwrap();
+        assert_eq!(selection, "p..q".parse().unwrap());
+        assert_eq!(selection.len(), 10);
+
+        let selection: KeySelection = "alphas,-wasd".parse().unwrap();
+        assert_eq!(selection.len(), 22);
+        assert!(!selection.contains(Keys::W));
+
+        let selection: KeySelection = "-all,space".parse().unwrap();
+        assert_eq!(selection.keys(), [Keys::Space]);
+
+        let selection: KeySelection = "-alphas".parse().unwrap();
+        assert_eq!(selection.len(), 68 - 26);
+        assert!(KeyGroup::Alphas
+            .keys()
+            .into_iter()
+            .all(|key| !selection.contains(key)));
+    }
+
+    #[test]
+    fn test_invalid_selection() {
+        assert!(matches!(
+            "escc".parse::<KeySelection>(),
+            Err(InvalidSelection::UnknownKey(key)) if key == "escc"
+        ));
+        assert!(matches!(
+            "q..z".parse::<KeySelection>(),
+            Err(InvalidSelection::RangeNotInRow(_))
+        ));
+        assert!(matches!(
+            " , ".parse::<KeySelection>(),
+            Err(InvalidSelection::Empty)
+        ));
+        assert!(matches!(
+            "wasd,-wasd".parse::<KeySelection>(),
+            Err(InvalidSelection::Empty)
+        ));
     }
 }
@@ -0,0 +1,212 @@
+use palette::Srgb;
+use strum::IntoEnumIterator;
+
+use crate::keyboards::steps::Steps;
+
+use super::{
+    keys::Keys, Animation, Brightness, Sleep, Speed, ANIMATION_LEN, ANIMATION_STEP_COUNT,
+    COLOR_DATA_LEN, COLOR_LEN, COLOR_STEP_COUNT,
+};
+
+/// A color setting rebuilt from the reports recorded by a
+/// [`MockTransport`](crate::keyboards::mock::MockTransport).
+#[derive(Clone, Debug)]
+pub struct ColorFrame {
+    pub steps: Steps<COLOR_DATA_LEN>,
+}
+
+impl ColorFrame {
+    /// Rebuild a color frame from the reports of a single `apply_color` call.
+    ///
+    /// Returns [`None`] if the reports do not have the shape of a color setting. Only the shape is
+    /// checked, the contents are left to the caller to assert on.
+    pub fn from_reports<R: AsRef<[u8]>>(reports: &[R]) -> Option<Self> {
+        if reports.len() != COLOR_STEP_COUNT {
+            return None;
+        }
+
+        let mut steps = Steps::new(COLOR_STEP_COUNT, COLOR_LEN, &[]);
+        for (step, report) in steps.steps_mut().zip(reports) {
+            let report = report.as_ref();
+            if report.len() != COLOR_LEN || report[..2] != [0x0A, 0x07] {
+                return None;
+            }
+
+            step.copy_from_slice(report);
+        }
+
+        Some(Self { steps })
+    }
+
+    /// The step counter byte of each step, in the order they were sent.
+    pub fn step_counters(&self) -> [u8; COLOR_STEP_COUNT] {
+        let mut counters = [0; COLOR_STEP_COUNT];
+        counters
+            .iter_mut()
+            .zip(self.steps.steps())
+            .for_each(|(counter, step)| *counter = step[2]);
+
+        counters
+    }
+
+    /// The raw sleep byte.
+    #[inline(always)]
+    pub fn sleep_byte(&self) -> u8 {
+        self.steps.data[5]
+    }
+
+    /// The sleep setting, if the sleep byte is a known value.
+    #[inline]
+    pub fn sleep(&self) -> Option<Sleep> {
+        Sleep::from_repr(self.sleep_byte())
+    }
+
+    /// The color that was sent for a key.
+    pub fn key_color(&self, key: Keys) -> Srgb<u8> {
+        let [r, g, b] = key.indexes().map(|index| self.steps[index]);
+
+        Srgb::new(r, g, b)
+    }
+
+    /// The color that was sent for each key.
+    pub fn key_colors(&self) -> impl Iterator<Item = (Keys, Srgb<u8>)> + '_ {
+        Keys::iter().map(|key| (key, self.key_color(key)))
+    }
+}
+
+/// An animation setting rebuilt from the report recorded by a
+/// [`MockTransport`](crate::keyboards::mock::MockTransport).
+#[derive(Clone, Debug)]
+pub struct AnimationFrame {
+    pub steps: Steps<ANIMATION_LEN>,
+}
+
+impl AnimationFrame {
+    /// Rebuild an animation frame from the report of a single `apply_animation` call.
+    ///
+    /// Returns [`None`] if the report does not have the shape of an animation setting.
+    pub fn from_report(report: &[u8]) -> Option<Self> {
+        if report.len() != ANIMATION_LEN || report[..2] != [0x0A, 0x01] {
+            return None;
+        }
+
+        let mut steps = Steps::new(ANIMATION_STEP_COUNT, ANIMATION_LEN, &[]);
+        steps.data.copy_from_slice(report);
+
+        Some(Self { steps })
+    }
+
+    #[inline]
+    pub fn animation(&self) -> Option<Animation> {
+        Animation::from_repr(self.steps.data[5])
+    }
+
+    #[inline]
+    pub fn speed(&self) -> Option<Speed> {
+        Speed::from_repr(self.steps.data[7])
+    }
+
+    #[inline]
+    pub fn brightness(&self) -> Option<Brightness> {
+        Brightness::from_repr(self.steps.data[8])
+    }
+
+    #[inline]
+    pub fn color(&self) -> Srgb<u8> {
+        let [r, g, b] = [9, 10, 11].map(|i| self.steps.data[i]);
+
+        Srgb::new(r, g, b)
+    }
+
+    #[inline(always)]
+    pub fn color_mix(&self) -> bool {
+        self.steps.data[12] != 0
+    }
+
+    #[inline]
+    pub fn sleep(&self) -> Option<Sleep> {
+        Sleep::from_repr(self.steps.data[13])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use palette::Srgb;
+    use strum::IntoEnumIterator;
+
+    use crate::keyboards::{
+        mock::MockTransport,
+        rk68::{keys::Keys, Animation, AnimationOptions, Rk68, Sleep, Speed},
+        KeyboardAnimatable, KeyboardAnimationOption, KeyboardColorOption, KeyboardColorable,
+        KeyboardPerKeyColorable,
+    };
+
+    use super::{AnimationFrame, ColorFrame};
+
+    #[test]
+    fn test_color_frame() {
+        let mock = MockTransport::new();
+        let red = Srgb::new(255, 0, 0);
+
+        Rk68::with_transport(mock.clone())
+            .set_color(red)
+            .set_color_parameters(Sleep::Never)
+            .apply_color()
+            .unwrap();
+
+        let reports = mock.reports();
+        assert_eq!(reports.len(), 7);
+        assert!(reports.iter().all(|report| report.len() == 65));
+
+        let frame = ColorFrame::from_reports(&reports).unwrap();
+        assert_eq!(frame.step_counters(), [1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(frame.sleep(), Some(Sleep::Never));
+        assert!(frame.key_colors().all(|(_, color)| color == red));
+    }
+
+    #[test]
+    fn test_color_frame_keys_do_not_overlap() {
+        let mock = MockTransport::new();
+        let mut kb = Rk68::with_transport(mock.clone());
+
+        // Give every key a unique color so that any shared index shows up as a mismatch.
+        let colors: Vec<_> = (0..68_u8).map(|i| Srgb::new(i, !i, i)).collect();
+        for (key, color) in Keys::iter().zip(&colors) {
+            kb = kb.set_key_color(key, *color);
+        }
+
+        for (key, color) in Keys::iter().zip(&colors) {
+            assert_eq!(kb.key_color(key), *color, "{key:?}");
+        }
+
+        kb.apply_color().unwrap();
+
+        let frame = ColorFrame::from_reports(&mock.reports()).unwrap();
+        for ((key, color), expected) in frame.key_colors().zip(&colors) {
+            assert_eq!(color, *expected, "{key:?}");
+        }
+    }
+
+    #[test]
+    fn test_animation_frame() {
+        let mock = MockTransport::new();
+
+        Rk68::with_transport(mock.clone())
+            .set_animation(Animation::Breathing)
+            .set_animation_parameters(AnimationOptions {
+                speed: Speed::Three,
+                color_mix: true,
+                ..Default::default()
+            })
+            .apply_animation()
+            .unwrap();
+
+        let reports = mock.reports();
+        assert_eq!(reports.len(), 1);
+
+        let frame = AnimationFrame::from_report(&reports[0]).unwrap();
+        assert_eq!(frame.animation(), Some(Animation::Breathing));
+        assert_eq!(frame.speed(), Some(Speed::Three));
+        assert!(frame.color_mix());
+    }
+}
@@ -1,16 +1,33 @@
+pub mod decode;
+pub mod dump;
 pub mod keys;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod profile;
+pub mod session;
+pub mod state;
+#[cfg(all(target_os = "linux", any(test, feature = "mock")))]
+pub mod uhid;
 
-use std::{fmt::Display, thread::sleep, time::Duration};
+use std::fmt::Display;
 
-use hidapi::{DeviceInfo, HidApi};
+use hidapi::{DeviceInfo, HidResult};
 use palette::Srgb;
-use strum::{EnumString, IntoEnumIterator, IntoStaticStr, VariantNames};
+use strum::{EnumString, FromRepr, IntoEnumIterator, IntoStaticStr, VariantNames};
 
 use self::keys::Keys;
 
+use crate::errors::ReadError;
+
 use super::{
-    steps::Steps, Keyboard, KeyboardAnimatable, KeyboardAnimationOption, KeyboardColorOption,
-    KeyboardColorable, Specs,
+    capture::{Capture, CaptureTransport},
+    layout::Layout,
+    on_interface,
+    policy::WritePolicy,
+    steps::{Indexes, Steps},
+    transport::{HidTransport, Transport, TransportDevice},
+    Keyboard, KeyboardAnimatable, KeyboardAnimationOption, KeyboardColorOption, KeyboardColorable,
+    KeyboardLayout, KeyboardPerKeyColorable, KeyboardReadable, Specs,
 };
 
 /// The number of steps needed for a color setting.
@@ -31,9 +48,14 @@ const ANIMATION_STEP_COUNT: usize = 1;
 /// Number of bytes needed to set an animation.
 const ANIMATION_LEN: usize = 65;
 
+/// A Royal Kludge RK68.
+///
+/// All writes go through the [`Transport`] the keyboard was constructed with, which defaults to
+/// [`HidTransport`].
 #[derive(Clone, Debug)]
-pub struct Rk68 {
-    device_info: DeviceInfo,
+pub struct Rk68<T: Transport = HidTransport> {
+    transport: T,
+    write_policy: WritePolicy,
     color_steps: Steps<COLOR_DATA_LEN>,
     animation_steps: Steps<ANIMATION_LEN>,
 }
@@ -52,6 +74,16 @@ impl Rk68 {
     /// The device being written to can be bricked, or bugged when a write is performed, if
     /// unsupported device information is passed.
     pub fn new_unchecked(device_info: DeviceInfo) -> Self {
+        Self::with_transport(HidTransport::new(device_info))
+    }
+}
+
+impl<T: Transport> Rk68<T> {
+    /// Create a keyboard that writes through the provided [`Transport`].
+    ///
+    /// No checks are performed on the transport, the same warnings as [`Rk68::new_unchecked`]
+    /// apply.
+    pub fn with_transport(transport: T) -> Self {
         let color_steps = {
             // 0x0A, and 0x07 are sent on each step. In a sense they serve as an indicator that
             // this is a color packet.
@@ -93,14 +125,99 @@ impl Rk68 {
         );
 
         Self {
-            device_info,
+            transport,
+            write_policy: WritePolicy::default(),
             color_steps,
             animation_steps,
         }
     }
+
+    /// The transport used to reach the keyboard.
+    #[inline(always)]
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Set how packets are written to the keyboard.
+    #[inline]
+    pub fn with_write_policy(mut self, write_policy: WritePolicy) -> Self {
+        self.write_policy = write_policy;
+        self
+    }
+
+    #[inline(always)]
+    pub fn write_policy(&self) -> &WritePolicy {
+        &self.write_policy
+    }
+
+    /// Record every report sent to, and read from the keyboard to `capture`.
+    ///
+    /// Passing [`None`] keeps the keyboard as it is, which makes it easy to only capture when
+    /// asked to.
+    pub fn with_capture(self, capture: Option<Capture>) -> Rk68<CaptureTransport<T>> {
+        Rk68 {
+            transport: CaptureTransport::new(self.transport, capture, ENDPOINT as u16),
+            write_policy: self.write_policy,
+            color_steps: self.color_steps,
+            animation_steps: self.animation_steps,
+        }
+    }
+
+    /// Set the color stored at the indexes of the red, green, and blue values, whether or not a
+    /// key uses them.
+    ///
+    /// Mostly useful for finding out which key uses which indexes, see [`color_slots`].
+    ///
+    /// # Panics:
+    /// Will cause a panic if any of the indexes are outside of the color steps.
+    pub fn set_indexes_color<C: Into<Srgb<u8>>>(
+        mut self,
+        indexes: &[Indexes; 3],
+        color: C,
+    ) -> Self {
+        let color: Srgb<u8> = color.into();
+
+        for (index, value) in indexes.iter().zip([color.red, color.green, color.blue]) {
+            self.color_steps[index.clone()] = value;
+        }
+
+        self
+    }
+
+    /// Send the color steps to an already opened device.
+    pub(crate) fn write_color<D: TransportDevice>(&self, device: &D) -> HidResult<()> {
+        let steps: Vec<&[u8]> = self.color_steps.steps().collect();
+
+        self.write_policy.write(device, &steps)
+    }
+
+    /// Send the animation step to an already opened device.
+    pub(crate) fn write_animation<D: TransportDevice>(&self, device: &D) -> HidResult<()> {
+        self.write_policy.write(device, &[&self.animation_steps])
+    }
+}
+
+/// Every group of three bytes in the color steps that the color of a key can be stored in.
+///
+/// Colors are stored back to back after the header of each step, and continue from one step to
+/// the next. Keys near the end of a step are split over two steps because of this.
+pub fn color_slots() -> Vec<[Indexes; 3]> {
+    // The first step also carries the color options, before any of the colors.
+    let indexes: Vec<Indexes> = (0..COLOR_STEP_COUNT)
+        .flat_map(|step| {
+            let first = if step == 0 { 6 } else { 3 };
+
+            (first..COLOR_LEN).map(move |index| Indexes::new(step, index))
+        })
+        .collect();
+
+    indexes
+        .chunks_exact(3)
+        .map(|slot| [slot[0].clone(), slot[1].clone(), slot[2].clone()])
+        .collect()
 }
 
-impl Specs for Rk68 {
+impl<T: Transport> Specs for Rk68<T> {
     const VID: u16 = 0x0258A;
     const PID: u16 = 0x005E;
 }
@@ -111,7 +228,7 @@ impl Keyboard for Rk68 {
             let color_device_index = devices.iter().position(|inf| {
                 inf.vendor_id() == Self::VID
                     && inf.product_id() == Self::PID
-                    && inf.interface_number() == Self::COLOR_ENDPOINT
+                    && on_interface(inf, Self::COLOR_ENDPOINT)
                     && inf.usage() == <Self as KeyboardColorable>::USAGE
                     && inf.usage_page() == <Self as KeyboardColorable>::USAGE_PAGE
             })?;
@@ -123,40 +240,57 @@ impl Keyboard for Rk68 {
     }
 }
 
-impl KeyboardColorable for Rk68 {
+impl<T: Transport> KeyboardColorable for Rk68<T> {
     const COLOR_ENDPOINT: i32 = ENDPOINT;
     const USAGE_PAGE: u16 = 1;
     const USAGE: u16 = 128;
-    fn set_color<C: Into<Srgb<u8>>>(mut self, color: C) -> Self {
-        let color: Srgb<u8> = color.into();
+    fn set_color<C: Into<Srgb<u8>>>(self, color: C) -> Self {
+        self.set_keys_color(Keys::iter(), color)
+    }
 
-        let colors = [color.red, color.green, color.blue];
+    fn apply_color(self) -> hidapi::HidResult<Self> {
+        let color_device = self.transport.open()?;
 
-        Keys::iter().map(|key| key.indexes()).for_each(|indexes| {
-            indexes.into_iter().zip(colors).for_each(|(index, color)| {
-                self.color_steps[index] = color;
-            })
-        });
+        self.write_color(&color_device)?;
 
-        self
+        color_device.close()?;
+
+        Ok(self)
     }
+}
 
-    fn apply_color(self) -> hidapi::HidResult<Self> {
-        let color_device = self.device_info.open_device(&HidApi::new()?)?;
+impl<T: Transport> KeyboardLayout for Rk68<T> {
+    type Key = Keys;
 
-        self.color_steps.steps().try_for_each(|step| {
-            let write_result = color_device.send_feature_report(step);
-            sleep(Duration::from_millis(5));
+    fn layout() -> Layout<Self::Key> {
+        Keys::layout()
+    }
+}
 
-            write_result
-        })?;
+impl<T: Transport> KeyboardPerKeyColorable for Rk68<T> {
+    type Key = Keys;
 
-        Ok(self)
+    fn set_key_color<C: Into<Srgb<u8>>>(self, key: Self::Key, color: C) -> Self {
+        self.set_indexes_color(&key.indexes(), color)
+    }
+
+    fn key_color(&self, key: Self::Key) -> Srgb<u8> {
+        let [red, green, blue] = key.indexes().map(|index| self.color_steps[index]);
+
+        Srgb::new(red, green, blue)
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, IntoStaticStr, VariantNames, EnumString)]
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, IntoStaticStr, VariantNames, EnumString, FromRepr,
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 #[strum(serialize_all = "kebab-case")]
+#[repr(u8)]
 pub enum Sleep {
     FiveMinutes = 1,
     #[default]
@@ -187,14 +321,19 @@ impl From<Sleep> for ColorOptions {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, rename_all = "kebab-case")
+)]
 pub struct ColorOptions {
     pub sleep: Sleep,
 }
 
-impl KeyboardColorOption for Rk68 {
+impl<T: Transport> KeyboardColorOption for Rk68<T> {
     type Options = ColorOptions;
-    fn set_color_parameters<T: Into<Self::Options>>(mut self, options: T) -> Self {
+    fn set_color_parameters<O: Into<Self::Options>>(mut self, options: O) -> Self {
         let options: Self::Options = options.into();
         self.color_steps.data[5] = options.sleep as u8;
 
@@ -202,8 +341,16 @@ impl KeyboardColorOption for Rk68 {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, IntoStaticStr, VariantNames, EnumString)]
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, IntoStaticStr, VariantNames, EnumString, FromRepr,
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 #[strum(serialize_all = "kebab-case")]
+#[repr(u8)]
 pub enum Animation {
     #[default]
     NeonStream = 1,
@@ -228,7 +375,7 @@ pub enum Animation {
     FlashAway = 20,
 }
 
-impl KeyboardAnimatable for Rk68 {
+impl<T: Transport> KeyboardAnimatable for Rk68<T> {
     const ANIMATION_ENDPOINT: i32 = ENDPOINT;
     const USAGE: u16 = 1;
     const USAGE_PAGE: u16 = 128;
@@ -238,26 +385,89 @@ impl KeyboardAnimatable for Rk68 {
         self
     }
 
-    fn apply_animation(self) -> hidapi::HidResult<()> {
-        let device = self.device_info.open_device(&HidApi::new()?)?;
+    fn apply_animation(self) -> hidapi::HidResult<Self> {
+        let device = self.transport.open()?;
+
+        self.write_animation(&device)?;
+
+        device.close()?;
 
-        device.send_feature_report(&self.animation_steps)?;
+        Ok(self)
+    }
+}
+
+impl<T: Transport> KeyboardReadable for Rk68<T> {
+    fn read_animation(&self) -> Result<(Animation, AnimationOptions), ReadError> {
+        let device = self.transport.open()?;
+
+        let result = self.read_animation_from(&device);
+        device.close()?;
+
+        result
+    }
+}
+
+impl<T: Transport> Rk68<T> {
+    /// Read the animation from an already opened device.
+    pub(crate) fn read_animation_from<D: TransportDevice>(
+        &self,
+        device: &D,
+    ) -> Result<(Animation, AnimationOptions), ReadError> {
+        // The report is read back in the same layout it is written in, starting with the report
+        // ID.
+        let mut report = [0; ANIMATION_LEN];
+        report[0] = self.animation_steps.data[0];
+
+        let len = device.get_feature_report(&mut report)?;
+        if len == 0 {
+            return Err(ReadError::NoResponse);
+        }
+
+        let report = &report[..len];
+        let invalid = || ReadError::InvalidResponse(report.to_vec());
+
+        if len < 14 || report[..5] != self.animation_steps.data[..5] {
+            return Err(invalid());
+        }
+
+        let animation = Animation::from_repr(report[5]).ok_or_else(invalid)?;
+        let options = AnimationOptions {
+            speed: Speed::from_repr(report[7]).ok_or_else(invalid)?,
+            brightness: Brightness::from_repr(report[8]).ok_or_else(invalid)?,
+            color: Srgb::new(report[9], report[10], report[11]),
+            color_mix: report[12] != 0,
+            sleep: Sleep::from_repr(report[13]).ok_or_else(invalid)?,
+        };
 
-        Ok(())
+        Ok((animation, options))
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, rename_all = "kebab-case")
+)]
 pub struct AnimationOptions {
     pub color_mix: bool,
+    #[cfg_attr(feature = "serde", serde(with = "crate::keyboards::color::hex"))]
     pub color: Srgb<u8>,
     pub speed: Speed,
     pub sleep: Sleep,
     pub brightness: Brightness,
 }
 
-#[derive(Clone, Copy, Debug, Default, VariantNames, IntoStaticStr, EnumString)]
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, VariantNames, IntoStaticStr, EnumString, FromRepr,
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 #[strum(serialize_all = "kebab-case")]
+#[repr(u8)]
 pub enum Speed {
     #[default]
     One = 1,
@@ -268,8 +478,16 @@ pub enum Speed {
 }
 
 /// Zero is off, each one after that is 20% of the total brightness.
-#[derive(Clone, Copy, Debug, Default, VariantNames, IntoStaticStr, EnumString)]
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, VariantNames, IntoStaticStr, EnumString, FromRepr,
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 #[strum(serialize_all = "kebab-case")]
+#[repr(u8)]
 pub enum Brightness {
     Zero = 0,
     One = 1,
@@ -280,9 +498,9 @@ pub enum Brightness {
     Five = 5,
 }
 
-impl KeyboardAnimationOption for Rk68 {
+impl<T: Transport> KeyboardAnimationOption for Rk68<T> {
     type Options = AnimationOptions;
-    fn set_animation_parameters<T: Into<Self::Options>>(mut self, options: T) -> Self {
+    fn set_animation_parameters<O: Into<Self::Options>>(mut self, options: O) -> Self {
         let options: Self::Options = options.into();
 
         // Set animation speed.
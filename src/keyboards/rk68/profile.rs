@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+
+use hidapi::HidResult;
+use palette::Srgb;
+use strum::IntoEnumIterator;
+
+use crate::keyboards::{
+    KeyboardAnimatable, KeyboardAnimationOption, KeyboardColorOption, KeyboardPerKeyColorable,
+};
+
+#[cfg(feature = "profiles")]
+use crate::{
+    errors::{ProfileError, ReadError},
+    keyboards::KeyboardReadable,
+    profiles::{ProfileStore, LAST_APPLIED},
+};
+
+use super::{keys::Keys, Animation, AnimationOptions, ColorOptions};
+
+/// A complete lighting setup for an [`Rk68`](super::Rk68), that can be applied in one go.
+///
+/// With the `serde` feature enabled, profiles are stored as a document tagged with the `mode`.
+/// Key, and option names are the same ones used by the command line.
+///
+/// ```toml
+/// mode = "static"
+///
+/// [options]
+/// sleep = "never"
+///
+/// [colors]
+/// esc = "#ff0000"
+/// w = "#00ff00"
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "mode", rename_all = "kebab-case")
+)]
+pub enum Profile {
+    /// A color for each key. Keys without a color are turned off.
+    Static {
+        #[cfg_attr(feature = "serde", serde(default))]
+        options: ColorOptions,
+        #[cfg_attr(
+            feature = "serde",
+            serde(default, with = "crate::keyboards::color::hex::map")
+        )]
+        colors: BTreeMap<Keys, Srgb<u8>>,
+    },
+
+    /// One of the animations built into the keyboard.
+    Animation {
+        animation: Animation,
+        #[cfg_attr(feature = "serde", serde(default))]
+        options: AnimationOptions,
+    },
+}
+
+impl Profile {
+    /// A static profile where every key has the same color.
+    pub fn color<C: Into<Srgb<u8>>>(color: C, options: ColorOptions) -> Self {
+        let color = color.into();
+
+        Self::colors(Keys::iter().map(|key| (key, color)), options)
+    }
+
+    /// A static profile from the color of each key.
+    pub fn colors<I>(colors: I, options: ColorOptions) -> Self
+    where
+        I: IntoIterator<Item = (Keys, Srgb<u8>)>,
+    {
+        Self::Static {
+            options,
+            colors: colors.into_iter().collect(),
+        }
+    }
+
+    /// Write the profile to the keyboard.
+    ///
+    /// Works with anything that can be configured like an [`Rk68`](super::Rk68), such as an
+    /// [`Rk68Session`](super::session::Rk68Session).
+    pub fn apply<KB>(&self, kb: KB) -> HidResult<KB>
+    where
+        KB: KeyboardPerKeyColorable<Key = Keys>
+            + KeyboardColorOption<Options = ColorOptions>
+            + KeyboardAnimatable<Animation = Animation>
+            + KeyboardAnimationOption<Options = AnimationOptions>,
+    {
+        match self {
+            Self::Static { options, colors } => colors
+                .iter()
+                .fold(kb.set_color(Srgb::new(0, 0, 0)), |kb, (key, color)| {
+                    kb.set_key_color(*key, *color)
+                })
+                .set_color_parameters(options.clone())
+                .apply_color(),
+            Self::Animation { animation, options } => kb
+                .set_animation(*animation)
+                .set_animation_parameters(options.clone())
+                .apply_animation(),
+        }
+    }
+}
+
+/// Where the settings returned by [`Profile::read`] came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// The keyboard reported them.
+    Device,
+
+    /// The keyboard did not answer, these are the settings last applied by kludged.
+    Recorded,
+}
+
+#[cfg(feature = "profiles")]
+impl Profile {
+    /// Read the current settings of a keyboard.
+    ///
+    /// If the keyboard can not be read, falls back to the last profile recorded under
+    /// [`LAST_APPLIED`] in the store returned by `store`, such as
+    /// [`ProfileStore::recorded`]. It is only called for the fallback, and the error from the
+    /// keyboard is returned if there is no store, or nothing was recorded in it.
+    pub fn read<KB, F>(kb: &KB, store: F) -> Result<(Self, Source), ReadError>
+    where
+        KB: KeyboardReadable<Animation = Animation>
+            + KeyboardAnimationOption<Options = AnimationOptions>,
+        F: FnOnce() -> Result<ProfileStore, ProfileError>,
+    {
+        match kb.read_animation() {
+            Ok((animation, options)) => {
+                Ok((Self::Animation { animation, options }, Source::Device))
+            }
+            Err(err) => match store().and_then(|store| store.load(LAST_APPLIED)) {
+                Ok(profile) => Ok((profile, Source::Recorded)),
+                Err(_) => Err(err),
+            },
+        }
+    }
+}
+
+#[cfg(all(test, feature = "profiles"))]
+mod tests {
+    use palette::Srgb;
+
+    use crate::keyboards::rk68::{
+        keys::Keys, AnimationOptions, Brightness, ColorOptions, Sleep, Speed,
+    };
+
+    use super::{Animation, Profile, Source};
+
+    #[test]
+    fn test_profile_round_trip() {
+        let profiles = [
+            Profile::colors(
+                [
+                    (Keys::Esc, Srgb::new(255, 0, 0)),
+                    (Keys::LeftShift, Srgb::new(0, 255, 18)),
+                ],
+                ColorOptions {
+                    sleep: Sleep::Never,
+                },
+            ),
+            Profile::Animation {
+                animation: Animation::RipplesShining,
+                options: AnimationOptions {
+                    color_mix: true,
+                    color: Srgb::new(1, 2, 3),
+                    speed: Speed::Four,
+                    sleep: Sleep::FiveMinutes,
+                    brightness: Brightness::Two,
+                },
+            },
+        ];
+
+        for profile in profiles {
+            let document = toml::to_string(&profile).unwrap();
+            assert_eq!(toml::from_str::<Profile>(&document).unwrap(), profile);
+        }
+    }
+
+    #[test]
+    fn test_profile_document() {
+        let profile: Profile = toml::from_str(
+            r##"
+            mode = "animation"
+            animation = "neon-stream"
+
+            [options]
+            color = "blue"
+            speed = "five"
+            color-mix = true
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(
+            profile,
+            Profile::Animation {
+                animation: Animation::NeonStream,
+                options: AnimationOptions {
+                    color: Srgb::new(0, 0, 255),
+                    speed: Speed::Five,
+                    color_mix: true,
+                    ..Default::default()
+                }
+            }
+        );
+
+        let profile: Profile = toml::from_str(
+            r##"
+            mode = "static"
+
+            [colors]
+            esc = "#ff0000"
+            left-shift = "green"
+            "##,
+        )
+        .unwrap();
+
+        let Profile::Static { options, colors } = profile else {
+            panic!("expected a static profile");
+        };
+        assert_eq!(options, ColorOptions::default());
+        assert_eq!(colors[&Keys::Esc], Srgb::new(255, 0, 0));
+        assert_eq!(colors[&Keys::LeftShift], Srgb::new(0, 128, 0));
+    }
+
+    #[test]
+    fn test_read() {
+        use std::{env, fs, process};
+
+        use crate::{
+            errors::ProfileError,
+            keyboards::{mock::MockTransport, rk68::Rk68},
+            profiles::{ProfileStore, LAST_APPLIED},
+        };
+
+        let dir = env::temp_dir().join(format!("kludged-read-{}", process::id()));
+        let store = ProfileStore::new(&dir);
+        let mock = MockTransport::new();
+        let kb = Rk68::with_transport(mock.clone());
+
+        let mut report = vec![0x0A, 0x01, 0x01, 0x02, 0x29, 20, 0x00, 3, 4, 1, 2, 3, 1, 5];
+        report.resize(65, 0);
+        mock.push_response(report);
+
+        let recorded_store = || Ok(store.clone());
+
+        let (profile, source) = Profile::read(&kb, recorded_store).unwrap();
+        assert_eq!(source, Source::Device);
+        assert_eq!(
+            profile,
+            Profile::Animation {
+                animation: Animation::FlashAway,
+                options: AnimationOptions {
+                    color_mix: true,
+                    color: Srgb::new(1, 2, 3),
+                    speed: Speed::Three,
+                    sleep: Sleep::Never,
+                    brightness: Brightness::Four,
+                },
+            }
+        );
+
+        // Nothing answers, and nothing was recorded.
+        assert!(Profile::read(&kb, recorded_store).is_err());
+        // There is nowhere to record settings either.
+        assert!(Profile::read(&kb, || Err(ProfileError::NoStateDir)).is_err());
+
+        let recorded = Profile::color(Srgb::new(255, 0, 0), ColorOptions::default());
+        store.save(LAST_APPLIED, &recorded).unwrap();
+        assert_eq!(
+            Profile::read(&kb, recorded_store).unwrap(),
+            (recorded, Source::Recorded)
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,426 @@
+use std::fmt::Debug;
+
+use hidapi::HidResult;
+use palette::Srgb;
+use strum::IntoEnumIterator;
+
+use crate::{
+    errors::{ReadError, SessionError},
+    keyboards::{
+        layout::Layout,
+        steps::Steps,
+        transport::{HidTransport, Transport, TransportDevice},
+        KeyboardAnimatable, KeyboardAnimationOption, KeyboardColorOption, KeyboardColorable,
+        KeyboardLayout, KeyboardPerKeyColorable, KeyboardReadable, Specs,
+    },
+};
+
+use super::{keys::Keys, Animation, AnimationOptions, ColorOptions, Rk68, COLOR_DATA_LEN};
+
+/// An [`Rk68`] with its device kept open.
+///
+/// [`Rk68::apply_color`](KeyboardColorable::apply_color) opens the device on every call, which
+/// is too slow to stream frames with. A session opens the device once, and reuses it for every
+/// apply until it is closed, or dropped.
+///
+/// The session can be configured the same way as an [`Rk68`]. Errors returned from the keyboard
+/// traits can be converted into a [`SessionError`] to check if the keyboard was disconnected, in
+/// which case [`Rk68Session::reconnect`] can be used once it is plugged back in.
+///
+/// ```no_run
+/// # use kludged::keyboards::{rk68::{session::Rk68Session, Rk68}, Keyboard, KeyboardColorable};
+/// # use palette::Srgb;
+/// let mut session = Rk68Session::open(Rk68::new()?)?;
+///
+/// for i in 0..=255 {
+///     session = session.set_color(Srgb::new(i, 0, 0)).apply_color()?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Rk68Session<T: Transport = HidTransport> {
+    kb: Rk68<T>,
+    device: T::Device,
+    write_mode: WriteMode,
+
+    /// The color steps as of the last successful write, if the keyboard is known to hold them.
+    last_color: Option<Steps<COLOR_DATA_LEN>>,
+    stats: WriteStats,
+}
+
+/// How a [`Rk68Session`] writes colors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Send every step on every write.
+    #[default]
+    Full,
+
+    /// Only send the steps that changed since the last write, and nothing at all if no step
+    /// changed.
+    ///
+    /// Each step carries its own step counter, and is a fixed size, so steps can be skipped but
+    /// not merged. Whether the firmware applies a partial update can be checked with
+    /// [`Rk68Session::partial_updates_honored`].
+    Diff,
+}
+
+/// Number of color steps a [`Rk68Session`] sent, and skipped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteStats {
+    /// Number of times the colors were written.
+    pub writes: u64,
+    pub steps_sent: u64,
+    pub steps_skipped: u64,
+}
+
+impl<T: Transport> Rk68Session<T> {
+    /// Open the device of a keyboard.
+    pub fn open(kb: Rk68<T>) -> Result<Self, SessionError> {
+        let device = kb.transport.open()?;
+
+        Ok(Self {
+            kb,
+            device,
+            write_mode: WriteMode::default(),
+            last_color: None,
+            stats: WriteStats::default(),
+        })
+    }
+
+    /// Set how colors are written.
+    #[inline]
+    pub fn with_write_mode(mut self, write_mode: WriteMode) -> Self {
+        self.write_mode = write_mode;
+        self
+    }
+
+    #[inline(always)]
+    pub fn write_mode(&self) -> WriteMode {
+        self.write_mode
+    }
+
+    /// Number of color steps sent, and skipped since the session was opened.
+    #[inline(always)]
+    pub fn stats(&self) -> WriteStats {
+        self.stats
+    }
+
+    /// The keyboard, and its stored settings.
+    #[inline(always)]
+    pub fn keyboard(&self) -> &Rk68<T> {
+        &self.kb
+    }
+
+    /// Open the device again, for example after it was unplugged.
+    ///
+    /// The next color write sends every step, as the keyboard may have lost its colors.
+    pub fn reconnect(&mut self) -> Result<(), SessionError> {
+        self.device = self.kb.transport.open()?;
+        self.last_color = None;
+
+        Ok(())
+    }
+
+    /// Write the stored colors to the keyboard, following the [`WriteMode`].
+    ///
+    /// Unlike [`KeyboardColorable::apply_color`], the session is kept if the write fails.
+    pub fn write_color(&mut self) -> Result<(), SessionError> {
+        let steps = &self.kb.color_steps;
+
+        let changed: Vec<&[u8]> = match (&self.last_color, self.write_mode) {
+            (Some(last), WriteMode::Diff) => steps
+                .steps()
+                .zip(last.steps())
+                .filter(|(step, last)| step != last)
+                .map(|(step, _)| step)
+                .collect(),
+            _ => steps.steps().collect(),
+        };
+
+        let sent = changed.len() as u64;
+        let skipped = steps.step_count as u64 - sent;
+        self.stats.writes += 1;
+
+        // If the write fails part way through, what the keyboard holds is unknown.
+        self.last_color = None;
+        self.kb.write_policy.write(&self.device, &changed)?;
+
+        self.stats.steps_sent += sent;
+        self.stats.steps_skipped += skipped;
+        self.last_color = Some(self.kb.color_steps.clone());
+
+        Ok(())
+    }
+
+    /// Write the stored animation to the keyboard.
+    ///
+    /// Unlike [`KeyboardAnimatable::apply_animation`], the session is kept if the write fails.
+    pub fn write_animation(&self) -> Result<(), SessionError> {
+        Ok(self.kb.write_animation(&self.device)?)
+    }
+
+    /// Close the device, and get back the keyboard.
+    pub fn close(self) -> Result<Rk68<T>, SessionError> {
+        self.device.close()?;
+
+        Ok(self.kb)
+    }
+
+    /// Check if the firmware applies colors when only some of the steps are sent.
+    ///
+    /// Turns every key off with a full write, then colors the keys of a single step, and only
+    /// sends that step. Returns the keys that should now be lit. If the firmware honors partial
+    /// updates exactly these keys light up, which can only be confirmed by looking at the
+    /// keyboard.
+    pub fn check_partial_update<C: Into<Srgb<u8>>>(
+        self,
+        color: C,
+    ) -> Result<(Self, Vec<Keys>), SessionError> {
+        // The second step, as it is the first one without the header.
+        const STEP: usize = 1;
+
+        let write_mode = self.write_mode;
+
+        let mut session = self
+            .with_write_mode(WriteMode::Full)
+            .set_color(Srgb::new(0, 0, 0));
+        session.write_color()?;
+
+        let keys: Vec<Keys> = Keys::iter()
+            .filter(|key| key.indexes().iter().all(|index| index.step == STEP))
+            .collect();
+
+        let mut session = session
+            .with_write_mode(WriteMode::Diff)
+            .set_keys_color(keys.iter().copied(), color);
+        session.write_color()?;
+
+        Ok((session.with_write_mode(write_mode), keys))
+    }
+
+    /// Check if the firmware honors partial updates, as used by [`WriteMode::Diff`].
+    ///
+    /// Runs [`Rk68Session::check_partial_update`], and passes the keys that should be lit to
+    /// `confirm`, which returns if exactly those keys lit up. Returns the session, and if partial
+    /// updates are honored.
+    pub fn partial_updates_honored<C, F>(
+        self,
+        color: C,
+        confirm: F,
+    ) -> Result<(Self, bool), SessionError>
+    where
+        C: Into<Srgb<u8>>,
+        F: FnOnce(&[Keys]) -> bool,
+    {
+        let (session, keys) = self.check_partial_update(color)?;
+        let honored = confirm(&keys);
+
+        Ok((session, honored))
+    }
+
+    #[inline]
+    fn map(self, f: impl FnOnce(Rk68<T>) -> Rk68<T>) -> Self {
+        Self {
+            kb: f(self.kb),
+            ..self
+        }
+    }
+}
+
+impl<T: Transport + Debug> Debug for Rk68Session<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Rk68Session")
+            .field("kb", &self.kb)
+            .field("write_mode", &self.write_mode)
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Transport> Specs for Rk68Session<T> {
+    const VID: u16 = <Rk68<T> as Specs>::VID;
+    const PID: u16 = <Rk68<T> as Specs>::PID;
+}
+
+impl<T: Transport> KeyboardColorable for Rk68Session<T> {
+    const COLOR_ENDPOINT: i32 = <Rk68<T> as KeyboardColorable>::COLOR_ENDPOINT;
+    const USAGE: u16 = <Rk68<T> as KeyboardColorable>::USAGE;
+    const USAGE_PAGE: u16 = <Rk68<T> as KeyboardColorable>::USAGE_PAGE;
+
+    fn set_color<C: Into<Srgb<u8>>>(self, color: C) -> Self {
+        self.map(|kb| kb.set_color(color))
+    }
+
+    fn apply_color(mut self) -> HidResult<Self> {
+        self.write_color()?;
+
+        Ok(self)
+    }
+}
+
+impl<T: Transport> KeyboardPerKeyColorable for Rk68Session<T> {
+    type Key = Keys;
+
+    fn set_key_color<C: Into<Srgb<u8>>>(self, key: Self::Key, color: C) -> Self {
+        self.map(|kb| kb.set_key_color(key, color))
+    }
+
+    #[inline]
+    fn key_color(&self, key: Self::Key) -> Srgb<u8> {
+        self.kb.key_color(key)
+    }
+}
+
+impl<T: Transport> KeyboardLayout for Rk68Session<T> {
+    type Key = Keys;
+
+    fn layout() -> Layout<Self::Key> {
+        Keys::layout()
+    }
+}
+
+impl<T: Transport> KeyboardColorOption for Rk68Session<T> {
+    type Options = ColorOptions;
+
+    fn set_color_parameters<O: Into<Self::Options>>(self, options: O) -> Self {
+        self.map(|kb| kb.set_color_parameters(options))
+    }
+}
+
+impl<T: Transport> KeyboardAnimatable for Rk68Session<T> {
+    const ANIMATION_ENDPOINT: i32 = <Rk68<T> as KeyboardAnimatable>::ANIMATION_ENDPOINT;
+    const USAGE: u16 = <Rk68<T> as KeyboardAnimatable>::USAGE;
+    const USAGE_PAGE: u16 = <Rk68<T> as KeyboardAnimatable>::USAGE_PAGE;
+    type Animation = Animation;
+
+    fn set_animation(self, animation: Self::Animation) -> Self {
+        self.map(|kb| kb.set_animation(animation))
+    }
+
+    fn apply_animation(self) -> HidResult<Self> {
+        self.write_animation()?;
+
+        Ok(self)
+    }
+}
+
+impl<T: Transport> KeyboardReadable for Rk68Session<T> {
+    fn read_animation(&self) -> Result<(Animation, AnimationOptions), ReadError> {
+        self.kb.read_animation_from(&self.device)
+    }
+}
+
+impl<T: Transport> KeyboardAnimationOption for Rk68Session<T> {
+    type Options = AnimationOptions;
+
+    fn set_animation_parameters<O: Into<Self::Options>>(self, options: O) -> Self {
+        self.map(|kb| kb.set_animation_parameters(options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use palette::Srgb;
+
+    use crate::{
+        errors::SessionError,
+        keyboards::{
+            mock::MockTransport,
+            rk68::{keys::Keys, Animation, Rk68},
+            KeyboardAnimatable, KeyboardColorable, KeyboardPerKeyColorable,
+        },
+    };
+
+    use super::{Rk68Session, WriteMode};
+
+    #[test]
+    fn test_session_reuses_device() {
+        let mock = MockTransport::new();
+        let mut session = Rk68Session::open(Rk68::with_transport(mock.clone())).unwrap();
+
+        for i in 0..3 {
+            session = session.set_color(Srgb::new(i, 0, 0)).apply_color().unwrap();
+        }
+        session = session
+            .set_animation(Animation::Neon)
+            .apply_animation()
+            .unwrap();
+
+        assert_eq!(mock.open_count(), 1);
+        assert_eq!(mock.reports().len(), 3 * 7 + 1);
+
+        session.close().unwrap();
+    }
+
+    #[test]
+    fn test_session_diff() {
+        let mock = MockTransport::new();
+        let session = Rk68Session::open(Rk68::with_transport(mock.clone()))
+            .unwrap()
+            .with_write_mode(WriteMode::Diff);
+
+        // Nothing was written yet, so every step is sent.
+        let session = session
+            .set_color(Srgb::new(255, 0, 0))
+            .apply_color()
+            .unwrap();
+        assert_eq!(mock.reports().len(), 7);
+
+        // Nothing changed.
+        let session = session.apply_color().unwrap();
+        assert_eq!(mock.reports().len(), 7);
+
+        // Esc is in the first step, and J in the third.
+        let session = session
+            .set_key_color(Keys::Esc, Srgb::new(0, 0, 255))
+            .set_key_color(Keys::J, Srgb::new(0, 0, 255))
+            .apply_color()
+            .unwrap();
+        let reports = mock.reports();
+        assert_eq!(reports.len(), 9);
+        assert_eq!((reports[7][2], reports[8][2]), (1, 3));
+
+        let stats = session.stats();
+        assert_eq!(stats.writes, 3);
+        assert_eq!(stats.steps_sent, 9);
+        assert_eq!(stats.steps_skipped, 12);
+
+        // A full write after diffing still knows the previous colors.
+        let (session, keys) = session.check_partial_update(Srgb::new(0, 255, 0)).unwrap();
+        assert!(keys.contains(&Keys::E) && !keys.contains(&Keys::Num3));
+        assert_eq!(mock.reports().len(), 9 + 7 + 1);
+        assert_eq!(session.write_mode(), WriteMode::Diff);
+
+        let (session, honored) = session
+            .partial_updates_honored(Srgb::new(0, 255, 0), |keys| keys.contains(&Keys::E))
+            .unwrap();
+        assert!(honored);
+        assert_eq!(mock.reports().len(), 9 + 2 * (7 + 1));
+
+        // Steps of a failed write are neither sent, nor skipped.
+        let stats = session.stats();
+        mock.fail_sends(0, usize::MAX);
+        let mut session = session.set_color(Srgb::new(1, 2, 3));
+        assert!(session.write_color().is_err());
+        assert_eq!(session.stats().steps_sent, stats.steps_sent);
+        assert_eq!(session.stats().steps_skipped, stats.steps_skipped);
+    }
+
+    #[test]
+    fn test_session_disconnect() {
+        let mock = MockTransport::new();
+        let mut session = Rk68Session::open(Rk68::with_transport(mock.clone())).unwrap();
+
+        mock.disconnect();
+        assert!(session.write_color().unwrap_err().is_disconnected());
+        assert!(session.reconnect().unwrap_err().is_disconnected());
+
+        let err = session.apply_color().unwrap_err();
+        assert!(SessionError::from(err).is_disconnected());
+
+        mock.reconnect();
+        let mut session = Rk68Session::open(Rk68::with_transport(mock.clone())).unwrap();
+        session.reconnect().unwrap();
+        session.write_color().unwrap();
+        assert_eq!(mock.open_count(), 3);
+    }
+}
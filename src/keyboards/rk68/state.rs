@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use palette::Srgb;
+use strum::IntoEnumIterator;
+
+use crate::keyboards::{
+    transport::Transport, KeyboardAnimatable, KeyboardAnimationOption, KeyboardColorOption,
+    KeyboardPerKeyColorable,
+};
+
+use super::{
+    keys::Keys, Animation, AnimationOptions, Brightness, ColorOptions, Rk68, Sleep, Speed,
+};
+
+/// A snapshot of the settings stored in an [`Rk68`].
+///
+/// Only the settings are captured, the transport (and with it the device) is not. This makes it
+/// possible to store the settings, and restore them on another instance with
+/// [`Rk68::set_state`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub struct Rk68State {
+    #[cfg_attr(feature = "serde", serde(with = "crate::keyboards::color::hex::map"))]
+    pub colors: BTreeMap<Keys, Srgb<u8>>,
+    pub color_options: ColorOptions,
+    pub animation: Animation,
+    pub animation_options: AnimationOptions,
+}
+
+impl<T: Transport> Rk68<T> {
+    /// Take a snapshot of the stored settings.
+    pub fn state(&self) -> Rk68State {
+        let animation = &self.animation_steps.data;
+
+        Rk68State {
+            colors: Keys::iter().map(|key| (key, self.key_color(key))).collect(),
+            color_options: ColorOptions {
+                sleep: Sleep::from_repr(self.color_steps.data[5]).unwrap_or_default(),
+            },
+            animation: Animation::from_repr(animation[5]).unwrap_or_default(),
+            animation_options: AnimationOptions {
+                color_mix: animation[12] != 0,
+                color: Srgb::new(animation[9], animation[10], animation[11]),
+                speed: Speed::from_repr(animation[7]).unwrap_or_default(),
+                sleep: Sleep::from_repr(animation[13]).unwrap_or_default(),
+                brightness: Brightness::from_repr(animation[8]).unwrap_or_default(),
+            },
+        }
+    }
+
+    /// Restore the settings from a snapshot.
+    ///
+    /// Keys missing from the snapshot keep their current color.
+    pub fn set_state(self, state: &Rk68State) -> Self {
+        state
+            .colors
+            .iter()
+            .fold(self, |kb, (key, color)| kb.set_key_color(*key, *color))
+            .set_color_parameters(state.color_options.clone())
+            .set_animation(state.animation)
+            .set_animation_parameters(state.animation_options.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use palette::Srgb;
+
+    use crate::keyboards::{
+        mock::MockTransport,
+        rk68::{keys::Keys, Animation, AnimationOptions, Brightness, Rk68, Sleep},
+        KeyboardAnimatable, KeyboardAnimationOption, KeyboardColorOption, KeyboardPerKeyColorable,
+    };
+
+    #[test]
+    fn test_state_round_trip() {
+        let kb = Rk68::with_transport(MockTransport::new())
+            .set_key_color(Keys::Esc, Srgb::new(1, 2, 3))
+            .set_color_parameters(Sleep::ThirtyMinutes)
+            .set_animation(Animation::Streamer)
+            .set_animation_parameters(AnimationOptions {
+                brightness: Brightness::Three,
+                color: Srgb::new(4, 5, 6),
+                ..Default::default()
+            });
+
+        let state = kb.state();
+        assert_eq!(state.colors[&Keys::Esc], Srgb::new(1, 2, 3));
+        assert_eq!(state.color_options.sleep, Sleep::ThirtyMinutes);
+        assert_eq!(state.animation, Animation::Streamer);
+        assert_eq!(state.animation_options.brightness, Brightness::Three);
+
+        let restored = Rk68::with_transport(MockTransport::new()).set_state(&state);
+        assert_eq!(restored.state(), state);
+        assert_eq!(restored.color_steps.data, kb.color_steps.data);
+        assert_eq!(restored.animation_steps.data, kb.animation_steps.data);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_names_match_strum() {
+        use std::str::FromStr;
+
+        use serde::{
+            de::{value::Error, IntoDeserializer},
+            Deserialize,
+        };
+        use strum::VariantNames;
+
+        use crate::keyboards::{
+            gradient::Interpolation,
+            rk68::{keys::KeyGroup, Speed},
+        };
+
+        // Every name the command line accepts should deserialize to the same value.
+        fn check<'de, T>()
+        where
+            T: VariantNames + FromStr + Deserialize<'de> + PartialEq + std::fmt::Debug,
+            T::Err: std::fmt::Debug,
+        {
+            for name in T::VARIANTS {
+                let value = T::deserialize(IntoDeserializer::<Error>::into_deserializer(*name));
+                assert_eq!(value.unwrap(), T::from_str(name).unwrap(), "{name}");
+            }
+        }
+
+        check::<Animation>();
+        check::<Sleep>();
+        check::<Speed>();
+        check::<Brightness>();
+        check::<Keys>();
+        check::<KeyGroup>();
+        check::<Interpolation>();
+    }
+}
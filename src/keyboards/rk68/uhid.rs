@@ -0,0 +1,341 @@
+//! A virtual RK68, created through the Linux [UHID](https://docs.kernel.org/hid/uhid.html)
+//! interface.
+//!
+//! The virtual keyboard shows up as a regular hidraw device, so discovery and every setting go
+//! through [`hidapi`] the same way they would with a real keyboard. This makes it possible to test
+//! the whole stack on a machine without an RK68 plugged in.
+//!
+//! Creating the device needs write access to `/dev/uhid`, which usually means running as root.
+//!
+//! ### Note:
+//! hidapi can not read the interface number of virtual devices, and reports it as `-1`. With the
+//! `mock` feature enabled, discovery accepts that in place of the interface the color settings
+//! are sent to.
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use kludged::keyboards::{
+//! #     rk68::{uhid::VirtualRk68, Rk68},
+//! #     selector::DeviceSelector,
+//! #     Keyboard, KeyboardAnimatable,
+//! # };
+//! let virtual_kb = VirtualRk68::create().unwrap();
+//! virtual_kb.wait(Duration::from_secs(5)).unwrap();
+//!
+//! Rk68::select(&DeviceSelector::Serial(virtual_kb.serial().to_owned()))
+//!     .unwrap()
+//!     .apply_animation()
+//!     .unwrap();
+//!
+//! assert_eq!(virtual_kb.reports().len(), 1);
+//! ```
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use hidapi::{DeviceInfo, HidApi};
+
+use crate::keyboards::{KeyboardColorable, Specs};
+
+use super::{Rk68, COLOR_LEN};
+
+const UHID_PATH: &str = "/dev/uhid";
+
+/// Size of `struct uhid_event`, which is the size of its largest member, `UHID_CREATE2`.
+const EVENT_LEN: usize = 280 + DATA_MAX;
+const DATA_MAX: usize = 4096;
+
+const UHID_DESTROY: u32 = 1;
+const UHID_STOP: u32 = 3;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+
+const UHID_FEATURE_REPORT: u8 = 0;
+const BUS_USB: u16 = 0x03;
+
+/// `EIO`, sent back for reports that are not feature reports.
+const EIO: u16 = 5;
+
+/// A single collection with the usage the keyboard is matched on, and a 64 byte feature report
+/// with the ID every setting is sent with.
+const REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x80, // Usage (System Control)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0x0A, //   Report ID (10)
+    0x06, 0x00, 0xFF, //   Usage Page (Vendor Defined)
+    0x09, 0x01, //   Usage (1)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x40, //   Report Count (64)
+    0xB1, 0x02, //   Feature (Data, Variable, Absolute)
+    0xC0, // End Collection
+];
+
+/// Gives each virtual keyboard of the process its own serial number.
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// A virtual RK68 that records every feature report sent to it.
+///
+/// The device is removed when dropped.
+#[derive(Debug)]
+pub struct VirtualRk68 {
+    uhid: File,
+    serial: String,
+    state: Arc<Mutex<VirtualState>>,
+    reader: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug, Default)]
+struct VirtualState {
+    reports: Vec<Vec<u8>>,
+    destroyed: bool,
+}
+
+impl VirtualRk68 {
+    /// Create the virtual device.
+    ///
+    /// The device is created right away, but it may take a moment for its hidraw node to show up.
+    /// Use [`VirtualRk68::wait`] before looking for it.
+    pub fn create() -> io::Result<Self> {
+        let mut uhid = OpenOptions::new().read(true).write(true).open(UHID_PATH)?;
+
+        let serial = format!(
+            "kludged-virtual-{}-{}",
+            std::process::id(),
+            CREATED.fetch_add(1, Ordering::Relaxed)
+        );
+
+        let mut event = Event::new(UHID_CREATE2);
+        event.put_bytes(4, b"Royal Kludge RK68 (virtual)");
+        event.put_bytes(132, b"kludged");
+        event.put_bytes(196, serial.as_bytes());
+        event.put_u16(260, REPORT_DESCRIPTOR.len() as u16);
+        event.put_u16(262, BUS_USB);
+        event.put_u32(264, <Rk68 as Specs>::VID.into());
+        event.put_u32(268, <Rk68 as Specs>::PID.into());
+        event.put_bytes(280, REPORT_DESCRIPTOR);
+        event.write_to(&mut uhid)?;
+
+        let state = Arc::new(Mutex::new(VirtualState::default()));
+        let reader = {
+            let uhid = uhid.try_clone()?;
+            let state = Arc::clone(&state);
+
+            thread::spawn(move || respond(uhid, state))
+        };
+
+        Ok(Self {
+            uhid,
+            serial,
+            state,
+            reader: Some(reader),
+        })
+    }
+
+    /// The serial number of the device, which can be used to select it over any real keyboard.
+    #[inline(always)]
+    pub fn serial(&self) -> &str {
+        &self.serial
+    }
+
+    /// Wait until hidapi lists the device, and return its information.
+    ///
+    /// Returns [`io::ErrorKind::TimedOut`] if the device did not show up within `timeout`.
+    pub fn wait(&self, timeout: Duration) -> io::Result<DeviceInfo> {
+        let start = Instant::now();
+
+        loop {
+            let hid_api = HidApi::new().map_err(io::Error::other)?;
+            let device_info = hid_api.device_list().find(|device_info| {
+                device_info.serial_number() == Some(self.serial.as_str())
+                    && device_info.usage() == <Rk68 as KeyboardColorable>::USAGE
+            });
+
+            if let Some(device_info) = device_info {
+                return Ok(device_info.clone());
+            }
+
+            if start.elapsed() > timeout {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Every feature report sent to the device so far, starting with the report ID.
+    pub fn reports(&self) -> Vec<Vec<u8>> {
+        self.state().reports.clone()
+    }
+
+    /// Forget all of the recorded reports.
+    pub fn clear(&self) {
+        self.state().reports.clear();
+    }
+
+    fn state(&self) -> MutexGuard<'_, VirtualState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Drop for VirtualRk68 {
+    fn drop(&mut self) {
+        self.state().destroyed = true;
+
+        // The kernel stops the device once it is destroyed, which lets the reader thread exit.
+        // If the device can not be destroyed, the reader is left to exit with the process.
+        if Event::new(UHID_DESTROY).write_to(&mut self.uhid).is_ok() {
+            if let Some(reader) = self.reader.take() {
+                let _ = reader.join();
+            }
+        }
+    }
+}
+
+/// Answer the requests of the kernel until the device is destroyed.
+fn respond(mut uhid: File, state: Arc<Mutex<VirtualState>>) {
+    let lock = || state.lock().unwrap_or_else(|err| err.into_inner());
+    let mut event = Event::new(0);
+
+    while event.read_from(&mut uhid).is_ok() {
+        let reply = match event.get_u32(0) {
+            UHID_STOP if lock().destroyed => break,
+            UHID_SET_REPORT => {
+                let size = usize::from(event.get_u16(10)).min(DATA_MAX);
+                let err = if event.get_u8(9) == UHID_FEATURE_REPORT {
+                    lock().reports.push(event.get_bytes(12, size).to_vec());
+                    0
+                } else {
+                    EIO
+                };
+
+                let mut reply = Event::new(UHID_SET_REPORT_REPLY);
+                reply.put_u32(4, event.get_u32(4));
+                reply.put_u16(8, err);
+                reply
+            }
+            UHID_GET_REPORT => {
+                // The keyboard has nothing to report, so an empty report is sent back.
+                let mut reply = Event::new(UHID_GET_REPORT_REPLY);
+                reply.put_u32(4, event.get_u32(4));
+                if event.get_u8(9) == UHID_FEATURE_REPORT {
+                    reply.put_u16(10, COLOR_LEN as u16);
+                    reply.put_bytes(12, &[event.get_u8(8)]);
+                } else {
+                    reply.put_u16(8, EIO);
+                }
+                reply
+            }
+            _ => continue,
+        };
+
+        if reply.write_to(&mut uhid).is_err() {
+            break;
+        }
+    }
+}
+
+/// A `struct uhid_event`, in the native byte order as the kernel expects it.
+struct Event([u8; EVENT_LEN]);
+
+impl Event {
+    fn new(kind: u32) -> Self {
+        let mut event = Self([0; EVENT_LEN]);
+        event.put_u32(0, kind);
+
+        event
+    }
+
+    fn read_from(&mut self, uhid: &mut File) -> io::Result<()> {
+        self.0.fill(0);
+        uhid.read(&mut self.0).map(|_| ())
+    }
+
+    fn write_to(&self, uhid: &mut File) -> io::Result<()> {
+        uhid.write_all(&self.0)
+    }
+
+    fn put_bytes(&mut self, offset: usize, bytes: &[u8]) {
+        self.0[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn put_u16(&mut self, offset: usize, value: u16) {
+        self.put_bytes(offset, &value.to_ne_bytes());
+    }
+
+    fn put_u32(&mut self, offset: usize, value: u32) {
+        self.put_bytes(offset, &value.to_ne_bytes());
+    }
+
+    fn get_bytes(&self, offset: usize, len: usize) -> &[u8] {
+        &self.0[offset..offset + len]
+    }
+
+    fn get_u8(&self, offset: usize) -> u8 {
+        self.0[offset]
+    }
+
+    fn get_u16(&self, offset: usize) -> u16 {
+        u16::from_ne_bytes([self.0[offset], self.0[offset + 1]])
+    }
+
+    fn get_u32(&self, offset: usize) -> u32 {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.get_bytes(offset, 4));
+
+        u32::from_ne_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use palette::named;
+
+    use crate::keyboards::{
+        rk68::Rk68, selector::DeviceSelector, transport::DryRunTransport, Keyboard,
+        KeyboardAnimatable, KeyboardColorable,
+    };
+
+    use super::VirtualRk68;
+
+    #[test]
+    #[ignore = "needs write access to /dev/uhid"]
+    fn test_virtual_keyboard() {
+        let virtual_kb = VirtualRk68::create().unwrap();
+        virtual_kb.wait(Duration::from_secs(5)).unwrap();
+
+        let selector = DeviceSelector::Serial(virtual_kb.serial().to_owned());
+        Rk68::select(&selector)
+            .unwrap()
+            .set_color(named::RED)
+            .apply_color()
+            .unwrap()
+            .apply_animation()
+            .unwrap();
+
+        let expected = DryRunTransport::new();
+        Rk68::with_transport(expected.clone())
+            .set_color(named::RED)
+            .apply_color()
+            .unwrap()
+            .apply_animation()
+            .unwrap();
+
+        assert_eq!(virtual_kb.reports(), expected.reports());
+    }
+}
@@ -0,0 +1,143 @@
+use std::{env, fmt::Display, str::FromStr};
+
+use hidapi::DeviceInfo;
+
+/// Environment variable for [`Target::from_env`].
+pub const DEVICE_ENV: &str = "KLUDGED_DEVICE";
+
+/// Picks out a single keyboard when several of the same model are plugged in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// The n-th keyboard of the model, counting from zero, in the order `kludged list` shows them.
+    Index(usize),
+
+    /// The keyboard whose path, as shown by `kludged list`, is exactly this.
+    Path(String),
+
+    /// The keyboard with this serial number.
+    Serial(String),
+}
+
+impl DeviceSelector {
+    /// Check if the device information belongs to the selected keyboard.
+    ///
+    /// ### Note:
+    /// An index can not be checked against a single device, and never matches.
+    pub fn matches(&self, device_info: &DeviceInfo) -> bool {
+        match self {
+            Self::Index(_) => false,
+            Self::Path(path) => device_info.path().to_string_lossy() == path.as_str(),
+            Self::Serial(serial) => device_info.serial_number() == Some(serial.as_str()),
+        }
+    }
+}
+
+impl FromStr for DeviceSelector {
+    type Err = String;
+
+    /// Values starting with `index:`, `path:`, or `serial:` are read as that kind of selector.
+    /// Otherwise numbers are read as an index, anything containing a `/` as a path, and anything
+    /// else as a serial number, so serial numbers made of digits need the `serial:` prefix.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let selector = if let Some(index) = s.strip_prefix("index:") {
+            Self::Index(
+                index
+                    .parse()
+                    .map_err(|_| format!("\"{index}\" is not a valid index."))?,
+            )
+        } else if let Some(path) = s.strip_prefix("path:") {
+            Self::Path(path.to_owned())
+        } else if let Some(serial) = s.strip_prefix("serial:") {
+            Self::Serial(serial.to_owned())
+        } else if let Ok(index) = s.parse() {
+            Self::Index(index)
+        } else if s.contains('/') {
+            Self::Path(s.to_owned())
+        } else {
+            Self::Serial(s.to_owned())
+        };
+
+        Ok(selector)
+    }
+}
+
+impl Display for DeviceSelector {
+    /// Prefixes the value only if it would not be read back as the same kind of selector.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (prefix, value) = match self {
+            Self::Index(index) => return write!(f, "{index}"),
+            Self::Path(path) => ("path", path),
+            Self::Serial(serial) => ("serial", serial),
+        };
+
+        if value.parse().as_ref() == Ok(self) {
+            write!(f, "{value}")
+        } else {
+            write!(f, "{prefix}:{value}")
+        }
+    }
+}
+
+/// Which of the plugged in keyboards of a model a setting is written to.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Target {
+    /// The first keyboard found.
+    #[default]
+    First,
+
+    /// A single selected keyboard.
+    Device(DeviceSelector),
+
+    /// Every keyboard of the model.
+    All,
+}
+
+impl Target {
+    /// [`Target::Device`] if `KLUDGED_DEVICE` is set, otherwise [`Target::First`].
+    ///
+    /// A selector that can not be parsed is ignored.
+    pub fn from_env() -> Self {
+        match env::var(DEVICE_ENV) {
+            Ok(device) if !device.trim().is_empty() => {
+                device.trim().parse().map_or(Self::First, Self::Device)
+            }
+            _ => Self::First,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeviceSelector;
+
+    #[test]
+    fn test_parse_selector() {
+        assert_eq!("1".parse(), Ok(DeviceSelector::Index(1)));
+        assert_eq!(
+            "/dev/hidraw3".parse(),
+            Ok(DeviceSelector::Path("/dev/hidraw3".to_owned()))
+        );
+        assert_eq!(
+            "A1B2C3".parse(),
+            Ok(DeviceSelector::Serial("A1B2C3".to_owned()))
+        );
+
+        // Serial numbers made of digits would be read as an index without the prefix.
+        let serial = DeviceSelector::Serial("0001".to_owned());
+        assert_eq!("serial:0001".parse(), Ok(serial.clone()));
+        assert_eq!(serial.to_string().parse(), Ok(serial));
+        assert_eq!("0001".parse(), Ok(DeviceSelector::Index(1)));
+
+        assert_eq!("index:2".parse(), Ok(DeviceSelector::Index(2)));
+        assert!("index:first".parse::<DeviceSelector>().is_err());
+        assert_eq!(
+            "path:hidraw3".parse(),
+            Ok(DeviceSelector::Path("hidraw3".to_owned()))
+        );
+        assert_eq!(
+            DeviceSelector::Path("hidraw3".to_owned()).to_string(),
+            "path:hidraw3"
+        );
+        assert_eq!(DeviceSelector::Index(1).to_string(), "1");
+    }
+}
@@ -95,7 +95,14 @@ impl<const DATA_LEN: usize> DerefMut for Steps<DATA_LEN> {
 }
 
 /// Indexes for the data that is being processed.
-#[derive(Clone, Debug)]
+///
+/// Serialized as a `[step, index]` pair.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "(usize, usize)", into = "(usize, usize)")
+)]
 pub struct Indexes {
     /// The N'th step.
     pub step: usize,
@@ -111,6 +118,20 @@ impl Indexes {
     }
 }
 
+impl From<(usize, usize)> for Indexes {
+    #[inline(always)]
+    fn from((step, index): (usize, usize)) -> Self {
+        Self::new(step, index)
+    }
+}
+
+impl From<Indexes> for (usize, usize) {
+    #[inline(always)]
+    fn from(value: Indexes) -> Self {
+        (value.step, value.index)
+    }
+}
+
 /// Create an array of [`Indexes`] with the length of `LEN`.
 pub(crate) const fn same_step_indexes<const LEN: usize>(
     step_count: usize,
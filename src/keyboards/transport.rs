@@ -0,0 +1,144 @@
+use std::sync::{Arc, Mutex};
+
+use hidapi::{DeviceInfo, HidApi, HidDevice, HidResult};
+
+/// A way of reaching a single keyboard.
+///
+/// Keyboards never talk to [`hidapi`] directly. Instead, they open a [`TransportDevice`] through
+/// the transport they were constructed with whenever a setting is applied. This makes it possible
+/// to swap out the backend, for example to drive a keyboard through another service, or to test
+/// without a keyboard being plugged in.
+pub trait Transport {
+    type Device: TransportDevice;
+
+    /// Open the device this transport points to.
+    fn open(&self) -> HidResult<Self::Device>;
+}
+
+/// An opened device that feature reports can be sent to, and read from.
+pub trait TransportDevice {
+    /// Send a feature report to the device.
+    ///
+    /// The first byte of `data` is the report ID, the rest is the report itself.
+    fn send_feature_report(&self, data: &[u8]) -> HidResult<()>;
+
+    /// Read a feature report from the device into `buf`.
+    ///
+    /// The first byte of `buf` should be set to the report ID before calling. Returns the number
+    /// of bytes read, including the report ID.
+    fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize>;
+
+    /// Close the device.
+    ///
+    /// By default the device is closed by dropping it. Implementations that need to flush, or
+    /// report errors on close should override this.
+    fn close(self) -> HidResult<()>
+    where
+        Self: Sized,
+    {
+        Ok(())
+    }
+}
+
+/// The default [`Transport`], backed by [`hidapi`].
+///
+/// A new [`HidApi`] instance is created each time the device is opened.
+#[derive(Clone, Debug)]
+pub struct HidTransport {
+    device_info: DeviceInfo,
+}
+
+impl HidTransport {
+    #[inline]
+    pub fn new(device_info: DeviceInfo) -> Self {
+        Self { device_info }
+    }
+
+    /// The information of the device this transport opens.
+    #[inline(always)]
+    pub fn device_info(&self) -> &DeviceInfo {
+        &self.device_info
+    }
+}
+
+impl From<DeviceInfo> for HidTransport {
+    fn from(value: DeviceInfo) -> Self {
+        Self::new(value)
+    }
+}
+
+impl Transport for HidTransport {
+    type Device = HidDevice;
+
+    fn open(&self) -> HidResult<Self::Device> {
+        self.device_info.open_device(&HidApi::new()?)
+    }
+}
+
+impl TransportDevice for HidDevice {
+    #[inline(always)]
+    fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+        HidDevice::send_feature_report(self, data)
+    }
+
+    #[inline(always)]
+    fn get_feature_report(&self, buf: &mut [u8]) -> HidResult<usize> {
+        HidDevice::get_feature_report(self, buf)
+    }
+}
+
+/// A [`Transport`] that keeps every feature report instead of sending it.
+///
+/// Used to see exactly what would be written to a keyboard, without one being plugged in. Clones
+/// share the same reports. Reading a feature report always returns zero bytes, as if the keyboard
+/// did not answer.
+///
+/// ```
+/// # use kludged::keyboards::{rk68::Rk68, transport::DryRunTransport, KeyboardColorable};
+/// let transport = DryRunTransport::new();
+/// Rk68::with_transport(transport.clone()).apply_color().unwrap();
+///
+/// assert_eq!(transport.reports().len(), 7);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DryRunTransport {
+    reports: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl DryRunTransport {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every feature report that would have been sent so far, in order.
+    pub fn reports(&self) -> Vec<Vec<u8>> {
+        self.reports.lock().unwrap().clone()
+    }
+
+    /// Take the reports that would have been sent so far, leaving none behind.
+    pub fn take_reports(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.reports.lock().unwrap())
+    }
+}
+
+impl Transport for DryRunTransport {
+    type Device = DryRunTransport;
+
+    #[inline]
+    fn open(&self) -> HidResult<Self::Device> {
+        Ok(self.clone())
+    }
+}
+
+impl TransportDevice for DryRunTransport {
+    fn send_feature_report(&self, data: &[u8]) -> HidResult<()> {
+        self.reports.lock().unwrap().push(data.to_vec());
+
+        Ok(())
+    }
+
+    fn get_feature_report(&self, _: &mut [u8]) -> HidResult<usize> {
+        Ok(0)
+    }
+}
@@ -4,5 +4,11 @@ pub mod cli;
 #[cfg(feature = "udev")]
 pub mod udev;
 
+#[cfg(feature = "profiles")]
+pub mod profiles;
+
+#[cfg(all(unix, feature = "daemon"))]
+pub mod daemon;
+
 pub mod errors;
 pub mod keyboards;
@@ -1,38 +1,168 @@
 use kludged::{
-    cli::{commons, rk68, Cli, Commands},
-    keyboards::KeyboardModels,
+    cli::{
+        commons, generic,
+        list::{write_list, ListFormat},
+        probe::{find_device, probe},
+        rk68, Cli, Commands,
+    },
+    keyboards::{
+        capture::Capture, definition::ModelDefinition, policy::WritePolicy, rk68::Rk68,
+        transport::DryRunTransport, KeyboardModels, Model,
+    },
     udev::rules,
 };
+#[cfg(target_family = "unix")]
+use kludged::{
+    daemon::{socket_path, Client, Daemon},
+    errors::DaemonError,
+};
 
 use std::{
     fs::File,
     io::{BufWriter, Write},
-    path::Path,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use clap::{Command, CommandFactory, Parser};
+use clap::{error::ErrorKind, Command, CommandFactory, Parser};
+use strum::VariantNames;
 
 use anyhow::{bail, Result};
-use color_print::cstr;
+use color_print::{cformat, cstr};
 
 fn main() -> Result<()> {
     let cmd = Cli::command().subcommand_required(true);
 
+    // Reported the same way as an invalid --packet-delay, instead of by whichever command reads
+    // the policy first.
+    if let Err(err) = WritePolicy::from_env() {
+        cmd.clone().error(ErrorKind::InvalidValue, err).exit();
+    }
+    let definitions = definitions();
+
     // Before passing the arguments to the keyboard handlers, we check if a non keyboard modifying
     // command was used.
     if let Ok(cli) = Cli::try_parse() {
-        if let Some(Commands::Udev { path }) = cli.command {
-            return handle_udev(&path);
+        match &cli.command {
+            Some(Commands::Udev { path }) => return handle_udev(path, &definitions),
+            Some(Commands::List { format }) => return handle_list(*format, &definitions),
+            Some(Commands::Decode { file }) => return rk68::handle_decode(file),
+            Some(Commands::Probe {
+                id,
+                interface,
+                output,
+            }) => return handle_probe(*id, *interface, output.as_deref(), &cli),
+            #[cfg(target_family = "unix")]
+            Some(Commands::Daemon { socket, stop }) => {
+                return handle_daemon(socket.clone(), *stop, &cli)
+            }
+            None => {}
         }
     };
 
-    handle_kb(cmd)?;
+    match forced_model(&definitions)? {
+        Some(ForcedModel::BuiltIn(model)) => handle_model(cmd, model)?,
+        Some(ForcedModel::Definition(definition)) => handle_definition(cmd, &definition)?,
+        // The daemon already holds a keyboard, so there is no need to look for one. Settings are
+        // sent to it by the model's commands.
+        None if daemon_running() => handle_model(cmd, Model::Rk68)?,
+        None => handle_kb(cmd, &definitions)?,
+    }
 
     Ok(())
 }
 
-fn handle_kb(cmd: Command) -> Result<()> {
-    let keyboards = KeyboardModels::keyboards()?;
+/// A model passed with `--model`.
+enum ForcedModel {
+    BuiltIn(Model),
+    Definition(Arc<ModelDefinition>),
+}
+
+/// The model passed with `--model`, found before the keyboard subcommands are known.
+///
+/// Anything after the first unknown subcommand is not looked at, which is why `--model` has to
+/// come before the keyboard's commands. Built in models are used over definitions of the same
+/// name.
+fn forced_model(definitions: &[Arc<ModelDefinition>]) -> Result<Option<ForcedModel>> {
+    let Some(name) = Cli::command()
+        .ignore_errors(true)
+        .allow_external_subcommands(true)
+        .disable_help_flag(true)
+        .try_get_matches()
+        .ok()
+        .and_then(|matches| matches.get_one::<String>("model").cloned())
+    else {
+        return Ok(None);
+    };
+
+    if let Ok(model) = name.parse() {
+        return Ok(Some(ForcedModel::BuiltIn(model)));
+    }
+
+    match definitions
+        .iter()
+        .find(|definition| definition.name == name)
+    {
+        Some(definition) => Ok(Some(ForcedModel::Definition(Arc::clone(definition)))),
+        None => {
+            let names: Vec<&str> = Model::VARIANTS
+                .iter()
+                .copied()
+                .chain(
+                    definitions
+                        .iter()
+                        .map(|definition| definition.name.as_str()),
+                )
+                .collect();
+
+            bail!(
+                "Unknown model \"{name}\", expected one of: {}.",
+                names.join(", ")
+            )
+        }
+    }
+}
+
+/// Whether a daemon answers on its socket.
+#[cfg(target_family = "unix")]
+fn daemon_running() -> bool {
+    match Client::from_env() {
+        Ok(Some(mut client)) => client.ping().is_ok(),
+        _ => false,
+    }
+}
+
+#[cfg(not(target_family = "unix"))]
+fn daemon_running() -> bool {
+    false
+}
+
+/// The models described in the configuration directory.
+///
+/// A broken definition should not keep the built in models, or the other definitions from
+/// working, so errors are only reported.
+fn definitions() -> Vec<Arc<ModelDefinition>> {
+    let warn = |message: String| eprintln!("{}", cformat!("<yellow>{message}</yellow>"));
+
+    match ModelDefinition::load_all() {
+        Ok(definitions) => definitions
+            .into_iter()
+            .filter_map(|definition| {
+                definition
+                    .map_err(|err| warn(format!("Ignoring the model definition {err}")))
+                    .ok()
+            })
+            .map(Arc::new)
+            .collect(),
+        Err(err) => {
+            warn(format!("Ignoring the model definitions: {err}"));
+            vec![]
+        }
+    }
+}
+
+fn handle_kb(cmd: Command, definitions: &[Arc<ModelDefinition>]) -> Result<()> {
+    let keyboards = KeyboardModels::keyboards_with_definitions(definitions)?;
 
     if keyboards.is_empty() {
         bail!(cstr!("<yellow>Unable to detect a supported keyboard. If you think this is incorrect please create an issue.</yellow>"))
@@ -41,15 +171,18 @@ fn handle_kb(cmd: Command) -> Result<()> {
     // If a single keyboard is found, there is no need to specify the device.
     // Instead, we just provide subcommands for the detected keyboard.
     if keyboards.len() == 1 {
-        return handle_single_kb(cmd, &keyboards[0]);
+        return match &keyboards[0] {
+            KeyboardModels::Rk68(_) => handle_single_kb(cmd, Model::Rk68),
+            KeyboardModels::Generic(kb) => handle_single_generic(cmd, kb.definition()),
+        };
     }
 
     handle_multiple_kb(cmd, keyboards)
 }
 
-fn handle_single_kb(mut cmd: Command, kb: &KeyboardModels) -> Result<()> {
-    match kb {
-        KeyboardModels::Rk68(_) => {
+fn handle_single_kb(mut cmd: Command, model: Model) -> Result<()> {
+    match model {
+        Model::Rk68 => {
             cmd = cmd
                 .after_help(commons::show_keyboard("RK68"))
                 .subcommands(rk68::single_kb_command());
@@ -60,18 +193,83 @@ fn handle_single_kb(mut cmd: Command, kb: &KeyboardModels) -> Result<()> {
     Ok(())
 }
 
+fn handle_single_generic(cmd: Command, definition: &ModelDefinition) -> Result<()> {
+    let matches = cmd
+        .after_help(commons::show_keyboard(&definition.name))
+        .subcommands(generic::single_kb_command(definition))
+        .get_matches();
+
+    generic::handle_args(&Arc::new(definition.clone()), &matches)
+}
+
+/// Provide the commands for a model without looking for a keyboard.
+///
+/// Both the commands used when a single keyboard is found, and the ones used when several are
+/// found are provided, so that scripts written for either can be checked.
+fn handle_model(mut cmd: Command, model: Model) -> Result<()> {
+    match model {
+        Model::Rk68 => {
+            cmd = rk68::command(cmd.subcommands(rk68::single_kb_command()));
+
+            let matches = cmd.get_matches();
+            match matches.subcommand() {
+                Some(("rk68", arg_matches)) => rk68::handle_args(arg_matches)?,
+                _ => rk68::handle_args(&matches)?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Provide the commands for a model described by a definition, without looking for a keyboard.
+///
+/// The same as [`handle_model`], for definitions.
+fn handle_definition(cmd: Command, definition: &Arc<ModelDefinition>) -> Result<()> {
+    let cmd = generic::command(
+        cmd.subcommands(generic::single_kb_command(definition)),
+        definition,
+    );
+
+    let matches = cmd.get_matches();
+    match matches.subcommand() {
+        Some((name, arg_matches)) if name == definition.name => {
+            generic::handle_args(definition, arg_matches)
+        }
+        _ => generic::handle_args(definition, &matches),
+    }
+}
+
 fn handle_multiple_kb(mut cmd: Command, keyboards: Vec<KeyboardModels>) -> Result<()> {
     // Add subcommands, and their arguments to the command.
-    for kb in keyboards.into_iter() {
+    for kb in keyboards.iter() {
+        // Several keyboards of the same model share a subcommand.
+        if cmd.find_subcommand(kb.name()).is_some() {
+            continue;
+        }
+
         cmd = match kb {
             KeyboardModels::Rk68(_) => rk68::command(cmd),
+            KeyboardModels::Generic(kb) => generic::command(cmd, kb.definition()),
         }
     }
 
     match cmd.get_matches().subcommand() {
         Some((subcommand_name, arg_matches)) => match subcommand_name {
             "rk68" => rk68::handle_args(arg_matches)?,
-            _ => todo!(),
+            name => {
+                let definition = keyboards.iter().find_map(|kb| match kb {
+                    KeyboardModels::Generic(kb) if kb.definition().name == name => {
+                        Some(kb.definition().clone())
+                    }
+                    _ => None,
+                });
+
+                match definition {
+                    Some(definition) => generic::handle_args(&Arc::new(definition), arg_matches)?,
+                    None => bail!("Subcommand not found."),
+                }
+            }
         },
         None => bail!("Subcommand not found."),
     }
@@ -79,7 +277,78 @@ fn handle_multiple_kb(mut cmd: Command, keyboards: Vec<KeyboardModels>) -> Resul
     Ok(())
 }
 
-fn handle_udev(path: &Path) -> Result<()> {
+#[cfg(target_family = "unix")]
+fn handle_daemon(socket: Option<PathBuf>, stop: bool, cli: &Cli) -> Result<()> {
+    let path = match socket {
+        Some(path) => path,
+        None => socket_path().ok_or(DaemonError::NoRuntimeDir)?,
+    };
+
+    if stop {
+        Client::connect(&path)?.shutdown()?;
+    } else {
+        let capture = cli.capture.as_ref().map(Capture::create).transpose()?;
+        let kb = rk68::keyboard(&cli.target)?
+            .with_write_policy(cli.write_policy)
+            .with_capture(capture);
+
+        Daemon::new(kb).run(path)?;
+    }
+
+    Ok(())
+}
+
+fn handle_list(format: ListFormat, definitions: &[Arc<ModelDefinition>]) -> Result<()> {
+    let keyboards: Vec<_> = KeyboardModels::keyboards_with_definitions(definitions)?
+        .iter()
+        .map(KeyboardModels::info)
+        .collect();
+
+    if keyboards.is_empty() && format == ListFormat::Table {
+        eprintln!(cstr!("<yellow>No supported keyboards were detected. If a keyboard is plugged in, check that you have permission to access it.</yellow>"));
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    write_list(&mut stdout, &keyboards, format)?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn handle_probe(
+    (vid, pid): (u16, u16),
+    interface: i32,
+    output: Option<&Path>,
+    cli: &Cli,
+) -> Result<()> {
+    let capture = cli.capture.as_ref().map(Capture::create).transpose()?;
+    let stdin = std::io::stdin().lock();
+    let stderr = std::io::stderr();
+
+    // Nothing is lit with --dry-run, which still makes it possible to try out the session.
+    let table = if cli.dry_run {
+        let kb = Rk68::with_transport(DryRunTransport::new()).with_write_policy(cli.write_policy);
+        probe(kb.with_capture(capture.clone()), stdin, stderr)?
+    } else {
+        let kb = Rk68::new_unchecked(find_device(vid, pid, interface)?)
+            .with_write_policy(cli.write_policy);
+        probe(kb.with_capture(capture.clone()), stdin, stderr)?
+    };
+
+    if let Some(capture) = capture {
+        capture.finish()?;
+    }
+
+    let layout = toml::to_string(&table)?;
+    match output {
+        Some(path) => std::fs::write(path, layout)?,
+        None => print!("{layout}"),
+    }
+
+    Ok(())
+}
+
+fn handle_udev(path: &Path, definitions: &[Arc<ModelDefinition>]) -> Result<()> {
     let mut buf = {
         let file = if !path.exists() {
             File::create_new(path)?
@@ -90,7 +359,7 @@ fn handle_udev(path: &Path) -> Result<()> {
         BufWriter::new(file)
     };
 
-    rules(&mut buf)?;
+    rules(&mut buf, definitions)?;
 
     buf.flush()?;
 
@@ -0,0 +1,196 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::errors::ProfileError;
+
+/// File extension used for profiles.
+const EXTENSION: &str = "toml";
+
+/// The directory kludged stores its configuration in.
+///
+/// Uses `$XDG_CONFIG_HOME/kludged`, or `$HOME/.config/kludged` if `$XDG_CONFIG_HOME` is not set.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".config")))?;
+
+    Some(base.join("kludged"))
+}
+
+/// The directory kludged stores state that should persist between runs in, such as the last
+/// applied setting.
+///
+/// Uses `$XDG_STATE_HOME/kludged`, or `$HOME/.local/state/kludged` if `$XDG_STATE_HOME` is not
+/// set.
+pub fn state_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".local/state")))?;
+
+    Some(base.join("kludged"))
+}
+
+/// Name the last applied profile is stored under, in [`ProfileStore::recorded`].
+pub const LAST_APPLIED: &str = "last-applied";
+
+/// A directory of named profiles, each stored as a TOML file.
+///
+/// The store is not tied to a profile type, any type that can be (de)serialized can be stored.
+/// For the profile of an RK68 see [`crate::keyboards::rk68::profile::Profile`].
+#[derive(Clone, Debug)]
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    #[inline]
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The default store, located in the `profiles` directory under [`config_dir`].
+    pub fn from_env() -> Result<Self, ProfileError> {
+        Ok(Self::new(
+            config_dir()
+                .ok_or(ProfileError::NoConfigDir)?
+                .join("profiles"),
+        ))
+    }
+
+    /// The store kludged records applied settings in, located in [`state_dir`].
+    ///
+    /// The last applied profile is stored as [`LAST_APPLIED`]. Keyboards are write-only for the
+    /// most part, so this is the only way of knowing what a keyboard is set to.
+    pub fn recorded() -> Result<Self, ProfileError> {
+        Ok(Self::new(state_dir().ok_or(ProfileError::NoStateDir)?))
+    }
+
+    #[inline(always)]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The path a profile is stored at.
+    ///
+    /// Returns an error if the name could escape the store directory, or would be a hidden file.
+    pub fn path(&self, name: &str) -> Result<PathBuf, ProfileError> {
+        let is_valid = !name.is_empty()
+            && !name.starts_with('.')
+            && !name.contains(['/', '\\'])
+            && !name.chars().any(char::is_control);
+
+        if !is_valid {
+            return Err(ProfileError::InvalidName(name.to_string()));
+        }
+
+        Ok(self.dir.join(format!("{name}.{EXTENSION}")))
+    }
+
+    /// Save a profile, replacing any existing profile with the same name.
+    pub fn save<P: Serialize>(&self, name: &str, profile: &P) -> Result<(), ProfileError> {
+        let path = self.path(name)?;
+        let document = toml::to_string_pretty(profile)?;
+
+        fs::create_dir_all(&self.dir)?;
+
+        // Write to a temporary file first so that a failed write never leaves a half written
+        // profile behind.
+        let tmp_path = path.with_extension(format!("{EXTENSION}.tmp"));
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(document.as_bytes())?;
+        file.sync_all()?;
+
+        fs::rename(tmp_path, path)?;
+
+        Ok(())
+    }
+
+    pub fn load<P: DeserializeOwned>(&self, name: &str) -> Result<P, ProfileError> {
+        let path = self.path(name)?;
+
+        let document = fs::read_to_string(path).map_err(|err| match err.kind() {
+            ErrorKind::NotFound => ProfileError::NotFound(name.to_string()),
+            _ => err.into(),
+        })?;
+
+        Ok(toml::from_str(&document)?)
+    }
+
+    /// Names of every stored profile, sorted alphabetically.
+    pub fn list(&self) -> Result<Vec<String>, ProfileError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut names = vec![];
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.extension().is_some_and(|ext| ext == EXTENSION) {
+                if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        names.sort();
+
+        Ok(names)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), ProfileError> {
+        fs::remove_file(self.path(name)?).map_err(|err| match err.kind() {
+            ErrorKind::NotFound => ProfileError::NotFound(name.to_string()),
+            _ => err.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use crate::{
+        errors::ProfileError,
+        keyboards::rk68::{profile::Profile, ColorOptions},
+    };
+
+    use super::ProfileStore;
+
+    #[test]
+    fn test_store() {
+        let dir = env::temp_dir().join(format!("kludged-profiles-{}", process::id()));
+        let store = ProfileStore::new(&dir);
+
+        assert!(store.list().unwrap().is_empty());
+
+        let profile = Profile::color(palette::Srgb::new(255, 0, 0), ColorOptions::default());
+        store.save("red", &profile).unwrap();
+        store.save("also red", &profile).unwrap();
+
+        assert_eq!(store.list().unwrap(), ["also red", "red"]);
+        assert_eq!(store.load::<Profile>("red").unwrap(), profile);
+
+        store.delete("red").unwrap();
+        assert!(matches!(
+            store.load::<Profile>("red"),
+            Err(ProfileError::NotFound(_))
+        ));
+        assert!(matches!(
+            store.path("../red"),
+            Err(ProfileError::InvalidName(_))
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
-use crate::keyboards::{rk68::Rk68, Keyboard};
+use crate::keyboards::{definition::ModelDefinition, rk68::Rk68, Keyboard};
 
-use std::io::Write;
+use std::{io::Write, sync::Arc};
 
 const SUBSYSTEMS: &[&str] = &["usb", "hidraw"];
 
@@ -14,37 +14,40 @@ where
     /// Write the rules into the provided buffer.
     ///
     /// Returns an error if the provided buffer returns an error on write.
-    fn rule<W: Write>(mut w_buf: W) -> std::io::Result<()> {
-        let vid = Self::VID;
-        let pid = Self::PID;
-
-        writeln!(w_buf)?;
-
-        for (i, subsystem) in SUBSYSTEMS.iter().enumerate() {
-            write!(
-                w_buf,
-                "\
-                SUBSYSTEM==\"{subsystem}\", \
-                ATTRS{{idVendor}}==\"{vid:04x}\", \
-                ATTRS{{idProduct}}==\"{pid:04x}\", \
-                MODE=\"0666\"\
-                ",
-            )?;
-
-            if i != SUBSYSTEMS.len() - 1 {
-                writeln!(w_buf)?;
-            }
-        }
+    fn rule<W: Write>(w_buf: W) -> std::io::Result<()> {
+        write_rule(w_buf, Self::VID, Self::PID)
+    }
+}
 
-        Ok(())
+/// Write the rules of a single vendor, and product ID into the provided buffer.
+fn write_rule<W: Write>(mut w_buf: W, vid: u16, pid: u16) -> std::io::Result<()> {
+    writeln!(w_buf)?;
+
+    for (i, subsystem) in SUBSYSTEMS.iter().enumerate() {
+        write!(
+            w_buf,
+            "\
+            SUBSYSTEM==\"{subsystem}\", \
+            ATTRS{{idVendor}}==\"{vid:04x}\", \
+            ATTRS{{idProduct}}==\"{pid:04x}\", \
+            MODE=\"0666\"\
+            ",
+        )?;
+
+        if i != SUBSYSTEMS.len() - 1 {
+            writeln!(w_buf)?;
+        }
     }
+
+    Ok(())
 }
 
-/// Write all of the rules into the provided buffer.
+/// Write all of the rules into the provided buffer, including the ones for the models described
+/// by `definitions`.
 ///
 /// Returns an error if the provided buffer returns an error on write. Before the rules are
 /// written, an informational text is prepended containing the version information.
-pub fn rules<W: Write>(w: &mut W) -> std::io::Result<()> {
+pub fn rules<W: Write>(w: &mut W, definitions: &[Arc<ModelDefinition>]) -> std::io::Result<()> {
     write!(
         w,
         "\
@@ -56,7 +59,13 @@ pub fn rules<W: Write>(w: &mut W) -> std::io::Result<()> {
         env!("CARGO_PKG_VERSION")
     )?;
 
-    Rk68::rule(w)?;
+    Rk68::rule(&mut *w)?;
+
+    for definition in definitions {
+        for id in &definition.ids {
+            write_rule(&mut *w, id.vid, id.pid)?;
+        }
+    }
 
     Ok(())
 }